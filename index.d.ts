@@ -1,8 +1,60 @@
+import { JsonWebKey, KeyObject } from 'crypto';
+
+/**
+ * A secret, a PEM or DER, a `KeyObject` or a JWK.
+ * A private key is PKCS#8, PKCS#1 or SEC1, an encrypted PKCS#8 key goes in `{ key, passphrase }`.
+ * A public key is SPKI or PKCS#1, or an X.509 certificate to take the public key of.
+ * A JWK is an `oct`, `RSA`, `EC` or `OKP` key, its `use`, `key_ops` and `alg` must allow the algorithm.
+ * `EdDSA` also takes the 32 raw bytes of an Ed25519 seed or public key, Ed448 is not supported.
+ * Raw bytes are a secret and an Ed25519 key alike, so `verify` rejects `algorithms` mixing them.
+ * An HMAC secret must be at least as long as the hash output, 32 bytes for HS256, an RSA modulus at least 2048 bits,
+ * unless `allowInsecureKeySizes`. An EC key must be on the curve of the algorithm.
+ * `null` is no key, for `none` tokens: `sign` then needs `allowUnsigned`, `verify` needs `'none'` in `algorithms`.
+ */
+export type Secret = string | Buffer | Uint8Array | KeyObject | JsonWebKey | EncryptedKey;
+
+/** An encrypted PKCS#8 private key, as a PEM or DER, and its passphrase. */
+export interface EncryptedKey {
+  key: string | Buffer | Uint8Array;
+  passphrase: string | Buffer;
+}
+
+/**
+ * A JWK Set, `verify` picks the key by the token `kid`, or by the `kty`, `crv` and `alg` that fit
+ * the token `alg` when it has none. No key or more than one key is an error.
+ */
+export interface JsonWebKeySet {
+  keys: JsonWebKey[];
+}
+
 export function sign(
   payload: object,
-  key: string,
+  key: Secret | null,
   options?: SignOptions,
 ): string;
+export function sign(
+  payload: object,
+  key: Secret | null,
+  callback: SignCallback,
+): void;
+export function sign(
+  payload: object,
+  key: Secret | null,
+  options: SignOptions | undefined,
+  callback: SignCallback,
+): void;
+
+/** Like `sign`, but signs on a worker thread instead of the event loop */
+export function signAsync(
+  payload: object,
+  key: Secret | null,
+  options?: SignOptions,
+): Promise<string>;
+
+export type SignCallback = (
+  error: JsonWebTokenError | Error | null,
+  token?: string,
+) => void;
 
 export function decode(
   token: string,
@@ -17,17 +69,213 @@ export function decode(
 
 export function verify(
   token: string,
-  secretOrPublicKey: string,
+  secretOrPublicKey: Secret | JsonWebKeySet | null,
   options: VerifyOptions & { complite: true },
 ): Jwt;
 export function verify(
   token: string,
-  secretOrPublicKey: string,
+  secretOrPublicKey: Secret | JsonWebKeySet | null,
   options?: VerifyOptions,
 ): object;
+export function verify(
+  token: string,
+  secretOrPublicKey: Secret | JsonWebKeySet | RemoteJwks | null,
+  callback: VerifyCallback<object>,
+): void;
+export function verify(
+  token: string,
+  secretOrPublicKey: Secret | JsonWebKeySet | RemoteJwks | null,
+  options: VerifyOptions & { complete: true },
+  callback: VerifyCallback<Jwt>,
+): void;
+export function verify(
+  token: string,
+  secretOrPublicKey: Secret | JsonWebKeySet | RemoteJwks | null,
+  options: VerifyOptions | undefined,
+  callback: VerifyCallback<object>,
+): void;
+
+/**
+ * Like `verify`, but verifies on a worker thread instead of the event loop.
+ * Only this and the callback form of `verify` take a `RemoteJwks`, which may fetch.
+ */
+export function verifyAsync(
+  token: string,
+  secretOrPublicKey: Secret | JsonWebKeySet | RemoteJwks | null,
+  options: VerifyOptions & { complete: true },
+): Promise<Jwt>;
+export function verifyAsync(
+  token: string,
+  secretOrPublicKey: Secret | JsonWebKeySet | RemoteJwks | null,
+  options?: VerifyOptions,
+): Promise<object>;
+
+export type VerifyCallback<T> = (
+  error: JsonWebTokenError | Error | null,
+  decoded?: T,
+) => void;
+
+/** Signs every payload, a payload that can not be signed gives its error in place of the token */
+export function signMany(
+  payloads: object[],
+  key: Secret | null,
+  options?: SignOptions & BatchOptions,
+): Array<string | Error>;
+
+/** Verifies every token, a token that does not verify gives its error in place of the payload */
+export function verifyMany(
+  tokens: string[],
+  secretOrPublicKey: Secret | JsonWebKeySet | null,
+  options: VerifyOptions & BatchOptions & { complete: true },
+): Array<Jwt | JsonWebTokenError | Error>;
+export function verifyMany(
+  tokens: string[],
+  secretOrPublicKey: Secret | JsonWebKeySet | null,
+  options?: VerifyOptions & BatchOptions,
+): Array<object | JsonWebTokenError | Error>;
+
+export interface BatchOptions {
+  /** Spread the batch across a thread pool */
+  parallel?: boolean | undefined;
+}
+
+/** Parses the key and options once, for signing many tokens */
+export function createSigner(key: Secret | null, options?: SignOptions): Signer;
+
+/** Parses the key and options once, for verifying many tokens */
+export function createVerifier(
+  secretOrPublicKey: Secret | JsonWebKeySet | null,
+  options: VerifyOptions & { complete: true },
+): Verifier<Jwt>;
+export function createVerifier(
+  secretOrPublicKey: Secret | JsonWebKeySet | null,
+  options?: VerifyOptions,
+): Verifier<object>;
+
+/**
+ * A JWK Set fetched from `url` when a token first needs it and cached. An unknown `kid` fetches it
+ * again at most once per `cooldown`, a failed fetch keeps the keys fetched before.
+ */
+export function createRemoteJwks(url: string, options?: RemoteJwksOptions): RemoteJwks;
+
+/** Time spans are seconds or strings like `"10m"` and `"500ms"`, of at most a year */
+export interface RemoteJwksOptions {
+  /** How long a fetched set is used, 10 minutes by default */
+  cacheMaxAge?: string | number | undefined;
+  /** The least time between fetches for an unknown `kid` or after a failure, 30 seconds by default */
+  cooldown?: string | number | undefined;
+  /** How long a fetch may take, 5 seconds by default */
+  timeout?: string | number | undefined;
+}
+
+export interface RemoteJwks {}
+
+/** The public JWK of a private or public key, without `kid`, `use` or `alg` unless given or on a JWK `key` */
+export function exportJwk(key: Secret, options?: ExportJwkOptions): JsonWebKey;
+
+/** The SPKI PEM of a private or public key */
+export function exportPublicPem(key: Secret): string;
+
+/**
+ * A JWK Set of the public keys, to publish for `verify`.
+ * A key without a `kid` gets its RFC 7638 thumbprint.
+ */
+export function toJwks(keys: Array<Secret | ({ key: Secret; passphrase?: string | Buffer } & ExportJwkOptions)>): JsonWebKeySet;
+
+/** The RFC 7638 thumbprint of a key, a secret's is that of its `oct` JWK */
+export function jwkThumbprint(key: Secret, hash?: 'sha256' | 'sha384' | 'sha512'): string;
+
+/**
+ * A new key pair for `alg`, the private key as a PKCS#8 PEM and the public key as a SPKI PEM,
+ * and both as JWKs with `alg`. RSA takes a while, the more so with a longer modulus.
+ */
+export function generateKeyPair(alg: Exclude<Algorithm, 'HS256' | 'HS384' | 'HS512' | 'none'>, options?: GenerateKeyPairOptions): KeyPair;
+
+/** A new secret for an HMAC `alg`, as long as the output of its hash */
+export function generateSecret(alg: 'HS256' | 'HS384' | 'HS512'): Buffer;
+
+export interface GenerateKeyPairOptions {
+  /** The RSA modulus in bits, a multiple of 8 from 2048 to 4096, 2048 by default */
+  modulusLength?: number | undefined;
+}
+
+export interface KeyPair {
+  privateKey: { pem: string; jwk: JsonWebKey };
+  publicKey: { pem: string; jwk: JsonWebKey };
+}
+
+export interface ExportJwkOptions {
+  kid?: string | undefined;
+  use?: string | undefined;
+  /** Must fit the key */
+  alg?: Algorithm | undefined;
+}
+
+export interface Signer {
+  sign(payload: object): string;
+}
+
+export interface Verifier<T> {
+  verify(token: string): T;
+}
+
+export class JsonWebTokenError extends Error {
+  code: ErrorCode;
+  /** The claim that failed validation, e.g. `'aud'` */
+  claim?: string;
+  /** What the options required for `claim` */
+  expected?: string | string[];
+  /** What the token had for `claim` */
+  actual?: any;
+  inner: Error;
+
+  constructor(message: string, error?: Error);
+}
+
+export class TokenExpiredError extends JsonWebTokenError {
+  expiredAt: Date;
+
+  constructor(message: string, expiredAt: Date);
+}
+
+export class NotBeforeError extends JsonWebTokenError {
+  date: Date;
+
+  constructor(message: string, date: Date);
+}
+
+export type ErrorCode =
+  | 'ERR_JWT'
+  | 'ERR_JWT_MALFORMED'
+  | 'ERR_JWT_INVALID'
+  | 'ERR_JWT_SIGNATURE_INVALID'
+  | 'ERR_JWT_SIGNATURE_REQUIRED'
+  | 'ERR_JWT_ALGORITHM_INVALID'
+  | 'ERR_JWT_AUDIENCE_MISMATCH'
+  | 'ERR_JWT_ISSUER_MISMATCH'
+  | 'ERR_JWT_SUBJECT_MISMATCH'
+  | 'ERR_JWT_JWTID_MISMATCH'
+  | 'ERR_JWT_NONCE_MISMATCH'
+  | 'ERR_JWT_CLAIM_INVALID'
+  | 'ERR_JWT_CLAIM_MISSING'
+  | 'ERR_JWT_EXPIRED'
+  | 'ERR_JWT_MAX_AGE_EXCEEDED'
+  | 'ERR_JWT_NOT_ACTIVE'
+  | 'ERR_JWKS_NO_MATCHING_KEY'
+  | 'ERR_JWKS_MULTIPLE_MATCHING_KEYS'
+  | 'ERR_JWKS_FETCH_FAILED'
+  | 'ERR_JWKS_INVALID'
+  | 'ERR_KEY_MISSING'
+  | 'ERR_KEY_PARSE'
+  | 'ERR_KEY_TYPE_MISMATCH'
+  | 'ERR_KEY_USAGE_MISMATCH'
+  | 'ERR_KEY_INSECURE'
+  | 'ERR_KEY_UNSUPPORTED'
+  | 'ERR_INVALID_ARGUMENT';
 
 export interface SignOptions {
   algorithm?: Algorithm | undefined;
+  /** The `kid`, `'thumbprint'` stamps the SHA-256 JWK thumbprint of the key */
   keyid?: string | undefined;
   expiresIn?: string | number | undefined;
   notBefore?: string | number | undefined;
@@ -36,7 +284,12 @@ export interface SignOptions {
   issuer?: string | undefined;
   jwtid?: string | undefined;
   noTimestamp?: boolean | undefined;
-  header?: JwtHeader | undefined;
+  /** Signs with `alg: none` when the key is `null`, never implied */
+  allowUnsigned?: boolean | undefined;
+  /** Accepts HMAC secrets shorter than the hash output and RSA moduli under 2048 bits, these sign in variable time (RUSTSEC-2023-0071) */
+  allowInsecureKeySizes?: boolean | undefined;
+  /** Merged over the generated `alg`, `typ` and `kid` */
+  header?: Partial<JwtHeader> | undefined;
 }
 
 export interface DecodeOptions {
@@ -46,16 +299,27 @@ export interface DecodeOptions {
 
 export interface VerifyOptions {
   algorithms?: Algorithm[] | undefined;
-  audience?: string | Array<string> | undefined;
+  audience?: string | RegExp | Array<string | RegExp> | undefined;
   complete?: boolean | undefined;
   issuer?: string | string[] | undefined;
   ignoreExpiration?: boolean | undefined;
   ignoreNotBefore?: boolean | undefined;
   jwtid?: string | undefined;
   subject?: string | undefined;
+  /** The `nonce` an OpenID Connect ID token must have */
+  nonce?: string | undefined;
+  /** Seconds of clock skew tolerated when checking `exp`, `nbf` and `maxAge` */
+  clockTolerance?: number | undefined;
+  /** The time to check the token as of, in seconds since the epoch */
+  clockTimestamp?: number | undefined;
+  /** The oldest `iat` accepted, in seconds or a vercel/ms time span string */
+  maxAge?: string | number | undefined;
+  /** Accepts HMAC secrets shorter than the hash output and RSA moduli under 2048 bits, for legacy tokens */
+  allowInsecureKeySizes?: boolean | undefined;
 }
 
 export interface JwtHeader {
+  [key: string]: any;
   alg: string | Algorithm;
   typ?: string | undefined;
   cty?: string | undefined;
@@ -92,6 +356,9 @@ export type Algorithm =
   | 'ES256'
   | 'ES384'
   | 'ES512'
+  | 'ES256K'
   | 'PS256'
   | 'PS384'
-  | 'PS512';
+  | 'PS512'
+  | 'EdDSA'
+  | 'none';
@@ -0,0 +1,241 @@
+'use strict';
+
+const { KeyObject } = require('crypto');
+const native = require('./index.node');
+
+class JsonWebTokenError extends Error {
+  constructor(message, error) {
+    super(message);
+    if (Error.captureStackTrace) {
+      Error.captureStackTrace(this, this.constructor);
+    }
+    this.name = 'JsonWebTokenError';
+    if (error) {
+      this.inner = error;
+    }
+  }
+}
+
+class NotBeforeError extends JsonWebTokenError {
+  constructor(message, date) {
+    super(message);
+    this.name = 'NotBeforeError';
+    this.date = date;
+  }
+}
+
+class TokenExpiredError extends JsonWebTokenError {
+  constructor(message, expiredAt) {
+    super(message);
+    this.name = 'TokenExpiredError';
+    this.expiredAt = expiredAt;
+  }
+}
+
+const errorClasses = {
+  JsonWebTokenError,
+  NotBeforeError,
+  TokenExpiredError,
+};
+
+// The native module throws plain `Error`s tagged with a `name`,
+// give them the prototype of the matching class so `instanceof` works.
+function typed(err) {
+  const ErrorClass = err instanceof Error && errorClasses[err.name];
+  if (ErrorClass) {
+    Object.setPrototypeOf(err, ErrorClass.prototype);
+  }
+  return err;
+}
+
+// The native module takes keys as PEM strings or `Buffer`s.
+function toNativeKey(key) {
+  if (key instanceof RemoteJwks) {
+    return key.handle;
+  }
+  if (key instanceof KeyObject) {
+    if (key.type === 'secret') {
+      return key.export();
+    }
+    const type = key.type === 'private' ? 'pkcs8' : 'spki';
+    return key.export({ format: 'pem', type });
+  }
+  if (key instanceof Uint8Array && !Buffer.isBuffer(key)) {
+    return Buffer.from(key.buffer, key.byteOffset, key.byteLength);
+  }
+  if (isWrappedKey(key)) {
+    return { ...key, key: toNativeKey(key.key) };
+  }
+  return key;
+}
+
+// `{ key, passphrase }` for an encrypted private key, `toJwks` also takes the members of the key.
+function isWrappedKey(key) {
+  return (
+    key !== null &&
+    typeof key === 'object' &&
+    !(key instanceof KeyObject) &&
+    !(key instanceof Uint8Array) &&
+    'key' in key &&
+    !('kty' in key)
+  );
+}
+
+// A `RegExp` has no own enumerable properties, pass its `source` and `flags` instead.
+function toNativeAudience(audience) {
+  if (audience instanceof RegExp) {
+    return { source: audience.source, flags: audience.flags };
+  }
+  return Array.isArray(audience) ? audience.map(toNativeAudience) : audience;
+}
+
+function toNativeVerifyOptions(options) {
+  if (!options || options.audience === undefined) {
+    return options;
+  }
+  return { ...options, audience: toNativeAudience(options.audience) };
+}
+
+function wrap(fn) {
+  return function (...args) {
+    try {
+      return fn.apply(this, args);
+    } catch (err) {
+      throw typed(err);
+    }
+  };
+}
+
+// Calls `fn` with a node style callback, which is `callback` or settles the returned `Promise`.
+// Errors thrown before the work is queued are handed over the same way.
+function callbackOrPromise(fn, callback) {
+  if (typeof callback !== 'function') {
+    return new Promise((resolve, reject) =>
+      callbackOrPromise(fn, (err, value) => (err ? reject(err) : resolve(value))),
+    );
+  }
+
+  const done = (err, value) => (err ? callback(typed(err)) : callback(null, value));
+  try {
+    fn(done);
+  } catch (err) {
+    done(err);
+  }
+}
+
+function signAsync(payload, key, options, callback) {
+  return callbackOrPromise(
+    (done) => native.signAsync(payload, toNativeKey(key), options, done),
+    callback,
+  );
+}
+
+function verifyAsync(token, key, options, callback) {
+  return callbackOrPromise(
+    (done) =>
+      native.verifyAsync(token, toNativeKey(key), toNativeVerifyOptions(options), done),
+    callback,
+  );
+}
+
+// `sign(payload, key[, options][, callback])`, like the npm package
+function sign(payload, key, options, callback) {
+  if (typeof options === 'function') {
+    return signAsync(payload, key, undefined, options);
+  }
+  if (typeof callback === 'function') {
+    return signAsync(payload, key, options, callback);
+  }
+  return native.sign(payload, toNativeKey(key), options);
+}
+
+// `verify(token, key[, options][, callback])`, like the npm package
+function verify(token, key, options, callback) {
+  if (typeof options === 'function') {
+    return verifyAsync(token, key, undefined, options);
+  }
+  if (typeof callback === 'function') {
+    return verifyAsync(token, key, options, callback);
+  }
+  return native.verify(token, toNativeKey(key), toNativeVerifyOptions(options));
+}
+
+// Each result is a value or the error of the item that failed.
+function signMany(payloads, key, options) {
+  return native.signMany(payloads, toNativeKey(key), options).map(typed);
+}
+
+function verifyMany(tokens, key, options) {
+  return native
+    .verifyMany(tokens, toNativeKey(key), toNativeVerifyOptions(options))
+    .map(typed);
+}
+
+// Holds a key and options the native module parsed once, see `createSigner`.
+class Signer {
+  constructor(key, options) {
+    this.handle = native.createSigner(toNativeKey(key), options);
+  }
+
+  sign(payload) {
+    return native.signerSign(this.handle, payload);
+  }
+}
+
+// Holds a key and options the native module parsed once, see `createVerifier`.
+class Verifier {
+  constructor(key, options) {
+    this.handle = native.createVerifier(
+      toNativeKey(key),
+      toNativeVerifyOptions(options),
+    );
+  }
+
+  verify(token) {
+    return native.verifierVerify(this.handle, token);
+  }
+}
+
+// A JWK Set the native module fetches and caches, see `createRemoteJwks`.
+class RemoteJwks {
+  constructor(url, options) {
+    this.handle = native.createRemoteJwks(url, options);
+  }
+}
+
+// `toJwks` takes keys, or `{ key, passphrase, kid, use, alg }` objects to set the members of a key.
+function toJwks(keys) {
+  const entries = keys.map((entry) => {
+    if (isWrappedKey(entry)) {
+      const { key, passphrase, ...options } = entry;
+      return [toNativeKey(passphrase === undefined ? key : { key, passphrase }), options];
+    }
+    return [toNativeKey(entry)];
+  });
+  return native.toJwks(entries);
+}
+
+Signer.prototype.sign = wrap(Signer.prototype.sign);
+Verifier.prototype.verify = wrap(Verifier.prototype.verify);
+
+module.exports = {
+  sign: wrap(sign),
+  signAsync: (payload, key, options) => signAsync(payload, key, options),
+  decode: wrap(native.decode),
+  verify: wrap(verify),
+  verifyAsync: (token, key, options) => verifyAsync(token, key, options),
+  signMany: wrap(signMany),
+  verifyMany: wrap(verifyMany),
+  createSigner: wrap((key, options) => new Signer(key, options)),
+  createVerifier: wrap((key, options) => new Verifier(key, options)),
+  createRemoteJwks: wrap((url, options) => new RemoteJwks(url, options)),
+  exportJwk: wrap((key, options) => native.exportJwk(toNativeKey(key), options)),
+  exportPublicPem: wrap((key) => native.exportPublicPem(toNativeKey(key))),
+  toJwks: wrap(toJwks),
+  jwkThumbprint: wrap((key, hash) => native.jwkThumbprint(toNativeKey(key), hash)),
+  generateKeyPair: wrap(native.generateKeyPair),
+  generateSecret: wrap(native.generateSecret),
+  JsonWebTokenError,
+  NotBeforeError,
+  TokenExpiredError,
+};
@@ -1,6 +1,7 @@
-use crate::jsonwebtoken_mod::header::ToObject;
+use crate::errors::OrThrow;
+use crate::jsonwebtoken_mod::decoding::dangerous_insecure_decode;
 use crate::neon_serde;
-use jsonwebtoken::dangerous_insecure_decode;
+use crate::utils::parse_options;
 use neon::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -9,12 +10,11 @@ use std::collections::HashMap;
 pub fn decode(mut cx: FunctionContext) -> JsResult<JsValue> {
   let jwt = cx.argument::<JsString>(0)?.value(&mut cx);
   let options = cx.argument_opt(1);
-  let decode_options: DecodeOptions =
-    neon_serde::from_value_opt(&mut cx, options).unwrap_or(DecodeOptions::default());
+  let decode_options: DecodeOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
 
-  let payload = dangerous_insecure_decode::<Claims>(&jwt).unwrap();
+  let (header, claims) = dangerous_insecure_decode::<Claims>(&jwt).or_throw(&mut cx)?;
 
-  let claim_object = neon_serde::to_value(&mut cx, &payload.claims).unwrap();
+  let claim_object = neon_serde::to_value(&mut cx, &claims).or_throw(&mut cx)?;
 
   if !decode_options.complete.unwrap_or(false) {
     return Ok(claim_object);
@@ -23,17 +23,13 @@ pub fn decode(mut cx: FunctionContext) -> JsResult<JsValue> {
   let decode_result = cx.empty_object();
   decode_result.set(&mut cx, "payload", claim_object)?;
 
-  let header = payload.header.to_object(&mut cx);
+  let header = neon_serde::to_value(&mut cx, &header).or_throw(&mut cx)?;
 
   decode_result.set(&mut cx, "header", header)?;
-  let signature = cx.string(jwt.split(".").nth(2).unwrap().to_string());
+  let signature = cx.string(jwt.split('.').nth(2).unwrap_or_default());
   decode_result.set(&mut cx, "signature", signature)?;
 
-  Ok(
-    decode_result
-      .downcast_or_throw::<JsValue, _>(&mut cx)
-      .unwrap(),
-  )
+  Ok(decode_result.upcast())
 }
 
 #[derive(Debug, Serialize, Deserialize)]
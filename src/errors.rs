@@ -0,0 +1,501 @@
+//! Defines the errors thrown to javascript by `sign`, `verify` and `decode`
+//! uses the `error-chain` crate for generation, like `neon_serde::errors`
+
+use crate::neon_serde;
+use jsonwebtoken::errors::ErrorKind as JwtErrorKind;
+use neon::prelude::*;
+use neon::result::Throw;
+use neon::types::JsDate;
+use serde::Serialize;
+use serde_json::Value;
+
+error_chain! {
+  links {
+    Serde(neon_serde::errors::Error, neon_serde::errors::ErrorKind);
+  }
+
+  foreign_links {
+    Json(serde_json::Error);
+  }
+
+  errors {
+    /// the token does not have the `header.payload.signature` shape
+    Malformed {
+      description("jwt malformed")
+      display("jwt malformed")
+    }
+    /// one of the token segments is not valid base64url, utf-8 or json
+    InvalidToken {
+      description("invalid token")
+      display("invalid token")
+    }
+    /// the signature does not match the header and payload
+    InvalidSignature {
+      description("invalid signature")
+      display("invalid signature")
+    }
+    /// the token `alg` is not allowed or does not fit the key
+    InvalidAlgorithm {
+      description("invalid algorithm")
+      display("invalid algorithm")
+    }
+    /// an option would overwrite a claim the payload already has
+    ClaimConflict(option: &'static str, claim: &'static str) {
+      description("option conflicts with a payload claim")
+      display("Bad \"options.{}\" option the payload already has an \"{}\" property.", option, claim)
+    }
+    /// an argument has a type that is not accepted
+    InvalidArgument(argument: &'static str, reason: &'static str) {
+      description("invalid argument")
+      display("{} {}", argument, reason)
+    }
+    /// an option has the right type but can not be used
+    InvalidOption(option: &'static str, reason: String) {
+      description("invalid option")
+      display("\"options.{}\" {}", option, reason)
+    }
+    /// the given secret or PEM can not be used as a key
+    InvalidKey {
+      description("invalid key")
+      display("key is not valid key material")
+    }
+    /// `verify` got no key for a signed token
+    MissingKey {
+      description("missing key")
+      display("secret or public key must be provided")
+    }
+    /// `verify` got a key for an unsecured token
+    SignatureRequired {
+      description("jwt signature is required")
+      display("jwt signature is required")
+    }
+    /// no key of a JWK Set fits the token `kid` and `alg`
+    NoMatchingKey {
+      description("no matching key")
+      display("no applicable key found in the JSON Web Key Set")
+    }
+    /// several keys of a JWK Set fit the token `kid` and `alg`
+    MultipleMatchingKeys {
+      description("multiple matching keys")
+      display("multiple matching keys found in the JSON Web Key Set")
+    }
+    /// a remote JWK Set could not be fetched
+    JwksFetch(reason: String) {
+      description("JSON Web Key Set fetch failed")
+      display("failed to fetch the JSON Web Key Set: {}", reason)
+    }
+    /// a remote JWK Set is not a JSON object with a `keys` array of JWKs
+    JwksInvalid {
+      description("JSON Web Key Set malformed")
+      display("JSON Web Key Set malformed")
+    }
+    /// the key is of a kind this crate can not use yet
+    UnsupportedKey(kind: String) {
+      description("unsupported key")
+      display("{} keys are not supported", kind)
+    }
+    /// the key is a secret where the `alg` needs a PEM key, or the other way around
+    InvalidKeyType(expected: &'static str, alg: &'static str) {
+      description("invalid key type")
+      display("key must be {} key when using {}", expected, alg)
+    }
+    /// the secret or modulus is shorter than the algorithm needs, `allowInsecureKeySizes` lets it through
+    InsecureKey(alg: &'static str, reason: String) {
+      description("insecure key")
+      display("{} requires {}", alg, reason)
+    }
+    /// a JWK `use`, `key_ops` or `alg` member does not allow what the key is asked to do
+    InvalidKeyUsage(member: &'static str, reason: String) {
+      description("invalid key usage")
+      display("the JWK \"{}\" member {}", member, reason)
+    }
+    /// `jti` does not match the `jwtid` option
+    InvalidJwtId(expected: String, actual: Option<Value>) {
+      description("jwt jwtid invalid")
+      display("jwt jwtid invalid. expected: {}", expected)
+    }
+    /// `nonce` does not match the `nonce` option
+    InvalidNonce(expected: String, actual: Option<Value>) {
+      description("jwt nonce invalid")
+      display("jwt nonce invalid. expected: {}", expected)
+    }
+    /// a registered claim does not have the type the RFC requires
+    InvalidClaim(claim: &'static str) {
+      description("invalid claim value")
+      display("invalid {} value", claim)
+    }
+    /// `aud` does not match the `audience` option
+    InvalidAudience(expected: Vec<String>, actual: Option<Value>) {
+      description("jwt audience invalid")
+      display("jwt audience invalid. expected: {}", expected.join(" or "))
+    }
+    /// `iss` does not match the `issuer` option
+    InvalidIssuer(expected: Vec<String>, actual: Option<Value>) {
+      description("jwt issuer invalid")
+      display("jwt issuer invalid. expected: {}", expected.join(","))
+    }
+    /// `sub` does not match the `subject` option
+    InvalidSubject(expected: String, actual: Option<Value>) {
+      description("jwt subject invalid")
+      display("jwt subject invalid. expected: {}", expected)
+    }
+    /// `exp` is in the past, thrown as `TokenExpiredError`
+    TokenExpired(expired_at: u64) {
+      description("jwt expired")
+      display("jwt expired")
+    }
+    /// `iat` is older than the `maxAge` option, thrown as `TokenExpiredError`
+    MaxAgeExceeded(expired_at: u64) {
+      description("maxAge exceeded")
+      display("maxAge exceeded")
+    }
+    /// the `maxAge` option needs the token to have an `iat`
+    MaxAgeWithoutIat {
+      description("iat required when maxAge is specified")
+      display("iat required when maxAge is specified")
+    }
+    /// `nbf` is in the future, thrown as `NotBeforeError`
+    NotBefore(date: u64) {
+      description("jwt not active")
+      display("jwt not active")
+    }
+  }
+}
+
+impl From<jsonwebtoken::errors::Error> for Error {
+  fn from(error: jsonwebtoken::errors::Error) -> Self {
+    match error.kind() {
+      JwtErrorKind::InvalidToken => ErrorKind::Malformed.into(),
+      JwtErrorKind::InvalidSignature | JwtErrorKind::Crypto(_) => {
+        ErrorKind::InvalidSignature.into()
+      }
+      JwtErrorKind::InvalidEcdsaKey
+      | JwtErrorKind::InvalidRsaKey
+      | JwtErrorKind::InvalidKeyFormat => ErrorKind::InvalidKey.into(),
+      JwtErrorKind::InvalidAlgorithm | JwtErrorKind::InvalidAlgorithmName => {
+        ErrorKind::InvalidAlgorithm.into()
+      }
+      // `verify` leaves the claims to `ClaimValidation`, which fills in the details
+      JwtErrorKind::InvalidAudience => ErrorKind::InvalidAudience(vec![], None).into(),
+      JwtErrorKind::InvalidIssuer => ErrorKind::InvalidIssuer(vec![], None).into(),
+      JwtErrorKind::InvalidSubject => ErrorKind::InvalidSubject(String::new(), None).into(),
+      // `ExpiredSignature` and `ImmatureSignature` can not happen, `ClaimValidation` checks
+      // `exp` and `nbf` with the dates they need, so they fall through with the rest
+      _ => ErrorKind::InvalidToken.into(),
+    }
+  }
+}
+
+impl Error {
+  /// The `name` of the thrown error, `index.js` swaps the prototype to the matching class
+  pub fn name(&self) -> &'static str {
+    match self.kind() {
+      ErrorKind::TokenExpired(_) | ErrorKind::MaxAgeExceeded(_) => "TokenExpiredError",
+      ErrorKind::NotBefore(_) => "NotBeforeError",
+      ErrorKind::Serde(_)
+      | ErrorKind::Json(_)
+      | ErrorKind::ClaimConflict(..)
+      | ErrorKind::InvalidOption(..)
+      | ErrorKind::InvalidArgument(..)
+      | ErrorKind::Msg(_) => "Error",
+      _ => "JsonWebTokenError",
+    }
+  }
+
+  /// The stable `code` of the thrown error, for logs and metrics
+  pub fn code(&self) -> &'static str {
+    match self.kind() {
+      ErrorKind::Malformed => "ERR_JWT_MALFORMED",
+      ErrorKind::InvalidToken => "ERR_JWT_INVALID",
+      ErrorKind::InvalidSignature => "ERR_JWT_SIGNATURE_INVALID",
+      ErrorKind::InvalidAlgorithm => "ERR_JWT_ALGORITHM_INVALID",
+      ErrorKind::InvalidKey => "ERR_KEY_PARSE",
+      ErrorKind::InvalidKeyType(..) => "ERR_KEY_TYPE_MISMATCH",
+      ErrorKind::InvalidKeyUsage(..) => "ERR_KEY_USAGE_MISMATCH",
+      ErrorKind::InsecureKey(..) => "ERR_KEY_INSECURE",
+      ErrorKind::UnsupportedKey(_) => "ERR_KEY_UNSUPPORTED",
+      ErrorKind::NoMatchingKey => "ERR_JWKS_NO_MATCHING_KEY",
+      ErrorKind::MultipleMatchingKeys => "ERR_JWKS_MULTIPLE_MATCHING_KEYS",
+      ErrorKind::JwksFetch(_) => "ERR_JWKS_FETCH_FAILED",
+      ErrorKind::JwksInvalid => "ERR_JWKS_INVALID",
+      ErrorKind::MissingKey => "ERR_KEY_MISSING",
+      ErrorKind::SignatureRequired => "ERR_JWT_SIGNATURE_REQUIRED",
+      ErrorKind::InvalidAudience(..) => "ERR_JWT_AUDIENCE_MISMATCH",
+      ErrorKind::InvalidIssuer(..) => "ERR_JWT_ISSUER_MISMATCH",
+      ErrorKind::InvalidSubject(..) => "ERR_JWT_SUBJECT_MISMATCH",
+      ErrorKind::InvalidJwtId(..) => "ERR_JWT_JWTID_MISMATCH",
+      ErrorKind::InvalidNonce(..) => "ERR_JWT_NONCE_MISMATCH",
+      ErrorKind::InvalidClaim(_) => "ERR_JWT_CLAIM_INVALID",
+      ErrorKind::TokenExpired(_) => "ERR_JWT_EXPIRED",
+      ErrorKind::MaxAgeExceeded(_) => "ERR_JWT_MAX_AGE_EXCEEDED",
+      ErrorKind::MaxAgeWithoutIat => "ERR_JWT_CLAIM_MISSING",
+      ErrorKind::NotBefore(_) => "ERR_JWT_NOT_ACTIVE",
+      ErrorKind::Serde(_)
+      | ErrorKind::Json(_)
+      | ErrorKind::ClaimConflict(..)
+      | ErrorKind::InvalidOption(..)
+      | ErrorKind::InvalidArgument(..) => "ERR_INVALID_ARGUMENT",
+      _ => "ERR_JWT",
+    }
+  }
+
+  /// The claim that failed validation, if any
+  pub fn claim(&self) -> Option<&'static str> {
+    match self.kind() {
+      ErrorKind::ClaimConflict(_, claim) | ErrorKind::InvalidClaim(claim) => Some(claim),
+      ErrorKind::InvalidAudience(..) => Some("aud"),
+      ErrorKind::InvalidIssuer(..) => Some("iss"),
+      ErrorKind::InvalidSubject(..) => Some("sub"),
+      ErrorKind::InvalidJwtId(..) => Some("jti"),
+      ErrorKind::InvalidNonce(..) => Some("nonce"),
+      ErrorKind::TokenExpired(_) => Some("exp"),
+      ErrorKind::MaxAgeExceeded(_) | ErrorKind::MaxAgeWithoutIat => Some("iat"),
+      ErrorKind::NotBefore(_) => Some("nbf"),
+      _ => None,
+    }
+  }
+
+  /// The date property of a `TokenExpiredError` or `NotBeforeError`, in seconds
+  pub fn date(&self) -> Option<(&'static str, u64)> {
+    match self.kind() {
+      ErrorKind::TokenExpired(expired_at) | ErrorKind::MaxAgeExceeded(expired_at) => {
+        Some(("expiredAt", *expired_at))
+      }
+      ErrorKind::NotBefore(date) => Some(("date", *date)),
+      _ => None,
+    }
+  }
+
+  /// Throws the error as a javascript exception
+  pub fn throw<'a, C: Context<'a>, T>(&self, cx: &mut C) -> NeonResult<T> {
+    // a pending javascript exception is already on its way
+    if let ErrorKind::Serde(neon_serde::errors::ErrorKind::Js(_)) = self.kind() {
+      return Err(Throw);
+    }
+
+    let js_error = self.to_js_error(cx)?;
+    cx.throw(js_error)
+  }
+
+  pub fn to_js_error<'a, C: Context<'a>>(&self, cx: &mut C) -> JsResult<'a, JsError> {
+    let error = JsError::error(cx, self.to_string())?;
+    let name = cx.string(self.name());
+    error.set(cx, "name", name)?;
+    let code = cx.string(self.code());
+    error.set(cx, "code", code)?;
+
+    if let Some(claim) = self.claim() {
+      let claim = cx.string(claim);
+      error.set(cx, "claim", claim)?;
+    }
+
+    if let Some((property, seconds)) = self.date() {
+      let date = JsDate::new_lossy(cx, seconds as f64 * 1000.0);
+      error.set(cx, property, date)?;
+    }
+
+    match self.kind() {
+      ErrorKind::InvalidAudience(expected, actual) | ErrorKind::InvalidIssuer(expected, actual) => {
+        set_mismatch(cx, error, expected, actual)?;
+      }
+      ErrorKind::InvalidSubject(expected, actual)
+      | ErrorKind::InvalidJwtId(expected, actual)
+      | ErrorKind::InvalidNonce(expected, actual) => {
+        set_mismatch(cx, error, expected, actual)?;
+      }
+      _ => {}
+    }
+
+    Ok(error)
+  }
+}
+
+/// Sets the `expected` and `actual` values of a claim mismatch
+fn set_mismatch<'a, C: Context<'a>, T: Serialize>(
+  cx: &mut C,
+  error: Handle<'a, JsError>,
+  expected: &T,
+  actual: &Option<Value>,
+) -> NeonResult<()> {
+  let expected = neon_serde::to_value(cx, expected).or_else(|e| cx.throw_error(e.to_string()))?;
+  error.set(cx, "expected", expected)?;
+  let actual = neon_serde::to_value(cx, actual).or_else(|e| cx.throw_error(e.to_string()))?;
+  error.set(cx, "actual", actual)?;
+  Ok(())
+}
+
+/// Throws the error of a failed `Result` as a javascript exception
+pub trait OrThrow<T> {
+  fn or_throw<'a, C: Context<'a>>(self, cx: &mut C) -> NeonResult<T>;
+}
+
+impl<T, E: Into<Error>> OrThrow<T> for std::result::Result<T, E> {
+  fn or_throw<'a, C: Context<'a>>(self, cx: &mut C) -> NeonResult<T> {
+    self.or_else(|error| error.into().throw(cx))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_names_and_dates() {
+    type Date = Option<(&'static str, u64)>;
+    let cases: Vec<(ErrorKind, &str, Date)> = vec![
+      (
+        ErrorKind::TokenExpired(10),
+        "TokenExpiredError",
+        Some(("expiredAt", 10)),
+      ),
+      (
+        ErrorKind::MaxAgeExceeded(20),
+        "TokenExpiredError",
+        Some(("expiredAt", 20)),
+      ),
+      (
+        ErrorKind::NotBefore(30),
+        "NotBeforeError",
+        Some(("date", 30)),
+      ),
+      (ErrorKind::Malformed, "JsonWebTokenError", None),
+      (ErrorKind::InvalidSignature, "JsonWebTokenError", None),
+      (ErrorKind::InvalidKey, "JsonWebTokenError", None),
+      (ErrorKind::MaxAgeWithoutIat, "JsonWebTokenError", None),
+      (
+        ErrorKind::InvalidAudience(vec!["a".to_string()], None),
+        "JsonWebTokenError",
+        None,
+      ),
+      (ErrorKind::ClaimConflict("expiresIn", "exp"), "Error", None),
+      (
+        ErrorKind::InvalidArgument("key", "must not be null"),
+        "Error",
+        None,
+      ),
+      (
+        ErrorKind::InvalidOption("algorithm", String::new()),
+        "Error",
+        None,
+      ),
+    ];
+
+    for (kind, name, date) in cases {
+      let error = Error::from(kind);
+      assert_eq!(error.name(), name, "{}", error);
+      assert_eq!(error.date(), date, "{}", error);
+    }
+
+    // no made up dates from `jsonwebtoken`, which does not check `exp` and `nbf` here
+    for kind in [
+      JwtErrorKind::ExpiredSignature,
+      JwtErrorKind::ImmatureSignature,
+    ] {
+      let error = Error::from(jsonwebtoken::errors::Error::from(kind));
+      assert_eq!(error.name(), "JsonWebTokenError", "{}", error);
+      assert_eq!(error.date(), None, "{}", error);
+    }
+  }
+
+  #[test]
+  fn test_codes() {
+    let json = serde_json::from_str::<Value>("{").unwrap_err();
+    let serde = neon_serde::errors::ErrorKind::ExpectingNull;
+    let cases = vec![
+      (ErrorKind::Malformed, "ERR_JWT_MALFORMED"),
+      (ErrorKind::InvalidToken, "ERR_JWT_INVALID"),
+      (ErrorKind::InvalidSignature, "ERR_JWT_SIGNATURE_INVALID"),
+      (ErrorKind::InvalidAlgorithm, "ERR_JWT_ALGORITHM_INVALID"),
+      (
+        ErrorKind::ClaimConflict("expiresIn", "exp"),
+        "ERR_INVALID_ARGUMENT",
+      ),
+      (
+        ErrorKind::InvalidArgument("key", "must not be null"),
+        "ERR_INVALID_ARGUMENT",
+      ),
+      (
+        ErrorKind::InvalidOption("algorithm", String::new()),
+        "ERR_INVALID_ARGUMENT",
+      ),
+      (ErrorKind::InvalidKey, "ERR_KEY_PARSE"),
+      (ErrorKind::MissingKey, "ERR_KEY_MISSING"),
+      (ErrorKind::SignatureRequired, "ERR_JWT_SIGNATURE_REQUIRED"),
+      (ErrorKind::NoMatchingKey, "ERR_JWKS_NO_MATCHING_KEY"),
+      (
+        ErrorKind::MultipleMatchingKeys,
+        "ERR_JWKS_MULTIPLE_MATCHING_KEYS",
+      ),
+      (ErrorKind::JwksFetch(String::new()), "ERR_JWKS_FETCH_FAILED"),
+      (ErrorKind::JwksInvalid, "ERR_JWKS_INVALID"),
+      (
+        ErrorKind::UnsupportedKey(String::new()),
+        "ERR_KEY_UNSUPPORTED",
+      ),
+      (
+        ErrorKind::InvalidKeyType("a secret", "HS256"),
+        "ERR_KEY_TYPE_MISMATCH",
+      ),
+      (
+        ErrorKind::InsecureKey("HS256", String::new()),
+        "ERR_KEY_INSECURE",
+      ),
+      (
+        ErrorKind::InvalidKeyUsage("use", String::new()),
+        "ERR_KEY_USAGE_MISMATCH",
+      ),
+      (
+        ErrorKind::InvalidJwtId(String::new(), None),
+        "ERR_JWT_JWTID_MISMATCH",
+      ),
+      (
+        ErrorKind::InvalidNonce(String::new(), None),
+        "ERR_JWT_NONCE_MISMATCH",
+      ),
+      (ErrorKind::InvalidClaim("exp"), "ERR_JWT_CLAIM_INVALID"),
+      (
+        ErrorKind::InvalidAudience(vec![], None),
+        "ERR_JWT_AUDIENCE_MISMATCH",
+      ),
+      (
+        ErrorKind::InvalidIssuer(vec![], None),
+        "ERR_JWT_ISSUER_MISMATCH",
+      ),
+      (
+        ErrorKind::InvalidSubject(String::new(), None),
+        "ERR_JWT_SUBJECT_MISMATCH",
+      ),
+      (ErrorKind::TokenExpired(0), "ERR_JWT_EXPIRED"),
+      (ErrorKind::MaxAgeExceeded(0), "ERR_JWT_MAX_AGE_EXCEEDED"),
+      (ErrorKind::MaxAgeWithoutIat, "ERR_JWT_CLAIM_MISSING"),
+      (ErrorKind::NotBefore(0), "ERR_JWT_NOT_ACTIVE"),
+      (ErrorKind::Json(json), "ERR_INVALID_ARGUMENT"),
+      (ErrorKind::Serde(serde), "ERR_INVALID_ARGUMENT"),
+      (ErrorKind::Msg(String::new()), "ERR_JWT"),
+    ];
+
+    for (kind, code) in cases {
+      let error = Error::from(kind);
+      assert_eq!(error.code(), code, "{}", error);
+    }
+  }
+
+  #[test]
+  fn test_claims() {
+    assert_eq!(Error::from(ErrorKind::TokenExpired(0)).claim(), Some("exp"));
+    assert_eq!(
+      Error::from(ErrorKind::MaxAgeWithoutIat).claim(),
+      Some("iat")
+    );
+    assert_eq!(Error::from(ErrorKind::NotBefore(0)).claim(), Some("nbf"));
+    assert_eq!(
+      Error::from(ErrorKind::InvalidClaim("aud")).claim(),
+      Some("aud")
+    );
+    assert_eq!(
+      Error::from(ErrorKind::InvalidNonce(String::new(), None)).claim(),
+      Some("nonce")
+    );
+    assert_eq!(Error::from(ErrorKind::InvalidKey).claim(), None);
+  }
+}
@@ -1,56 +1,282 @@
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use crate::errors::{ErrorKind, Result};
+use crate::jsonwebtoken_mod::crypto::{DecodingKey, EncodingKey};
+use crate::jsonwebtoken_mod::jwk::KeyOperation;
+use crate::jsonwebtoken_mod::key_material::KeyMaterial;
+use crate::jsonwebtoken_mod::key_pair::KeyPair;
+use crate::jsonwebtoken_mod::public_key::PublicKey;
+use crate::jsonwebtoken_mod::{ec, eddsa, rsa};
+use ::rsa::traits::PublicKeyParts;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// The `alg` of a token, `jsonwebtoken::Algorithm` plus the algorithms it does not implement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Algorithm {
+  HS256,
+  HS384,
+  HS512,
+  ES256,
+  ES384,
+  /// ECDSA with P-521 and SHA-512
+  ES512,
+  /// ECDSA with secp256k1 and SHA-256, RFC 8812
+  ES256K,
+  RS256,
+  RS384,
+  RS512,
+  PS256,
+  PS384,
+  PS512,
+  /// Ed25519, Ed448 is not implemented
+  EdDSA,
+  /// an unsecured token, RFC 7519 section 6
+  #[serde(rename = "none")]
+  None,
+}
+
+/// The kind of key an algorithm takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlgorithmFamily {
+  Hmac,
+  Rsa,
+  Ec,
+  Okp,
+  /// `none` takes no key
+  Unsecured,
+}
+
+impl Algorithm {
+  pub fn family(self) -> AlgorithmFamily {
+    match self {
+      Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => AlgorithmFamily::Hmac,
+      Algorithm::RS256
+      | Algorithm::RS384
+      | Algorithm::RS512
+      | Algorithm::PS256
+      | Algorithm::PS384
+      | Algorithm::PS512 => AlgorithmFamily::Rsa,
+      Algorithm::ES256 | Algorithm::ES384 | Algorithm::ES512 | Algorithm::ES256K => {
+        AlgorithmFamily::Ec
+      }
+      Algorithm::EdDSA => AlgorithmFamily::Okp,
+      Algorithm::None => AlgorithmFamily::Unsecured,
+    }
+  }
+
+  /// The JWK `crv` of the key an ECDSA algorithm takes
+  pub fn curve(self) -> Option<&'static str> {
+    match self {
+      Algorithm::ES256 => Some("P-256"),
+      Algorithm::ES384 => Some("P-384"),
+      Algorithm::ES512 => Some("P-521"),
+      Algorithm::ES256K => Some("secp256k1"),
+      _ => None,
+    }
+  }
+
+  /// The same algorithm in `jsonwebtoken`, which does the signing for it
+  pub fn to_jsonwebtoken(self) -> Option<jsonwebtoken::Algorithm> {
+    let alg = match self {
+      Algorithm::HS256 => jsonwebtoken::Algorithm::HS256,
+      Algorithm::HS384 => jsonwebtoken::Algorithm::HS384,
+      Algorithm::HS512 => jsonwebtoken::Algorithm::HS512,
+      Algorithm::ES256 => jsonwebtoken::Algorithm::ES256,
+      Algorithm::ES384 => jsonwebtoken::Algorithm::ES384,
+      Algorithm::RS256 => jsonwebtoken::Algorithm::RS256,
+      Algorithm::RS384 => jsonwebtoken::Algorithm::RS384,
+      Algorithm::RS512 => jsonwebtoken::Algorithm::RS512,
+      Algorithm::PS256 => jsonwebtoken::Algorithm::PS256,
+      Algorithm::PS384 => jsonwebtoken::Algorithm::PS384,
+      Algorithm::PS512 => jsonwebtoken::Algorithm::PS512,
+      Algorithm::ES512 | Algorithm::ES256K | Algorithm::EdDSA | Algorithm::None => return None,
+    };
+
+    Some(alg)
+  }
+}
 
 pub trait AsStr {
-  fn as_str(&self) -> &str;
+  fn as_str(&self) -> &'static str;
 }
 
 impl AsStr for Algorithm {
-  fn as_str(&self) -> &str {
+  fn as_str(&self) -> &'static str {
     match self {
       Algorithm::HS256 => "HS256",
       Algorithm::HS384 => "HS384",
       Algorithm::HS512 => "HS512",
       Algorithm::ES256 => "ES256",
       Algorithm::ES384 => "ES384",
+      Algorithm::ES512 => "ES512",
+      Algorithm::ES256K => "ES256K",
       Algorithm::RS256 => "RS256",
       Algorithm::RS384 => "RS384",
       Algorithm::PS256 => "PS256",
       Algorithm::PS384 => "PS384",
       Algorithm::PS512 => "PS512",
       Algorithm::RS512 => "RS512",
+      Algorithm::EdDSA => "EdDSA",
+      Algorithm::None => "none",
     }
   }
 }
 
 pub trait Key {
-  fn get_encoding_key(self, key: &[u8]) -> EncodingKey;
-  fn get_decoding_key(self, key: &[u8]) -> DecodingKey;
+  fn get_encoding_key(self, key: &KeyMaterial) -> Result<EncodingKey>;
+  fn get_decoding_key(self, key: &KeyMaterial) -> Result<DecodingKey>;
+  /// Checks the secret is as long as the output of the hash, RFC 7518 section 3.2,
+  /// and the RSA modulus has at least 2048 bits, section 3.3
+  fn check_key_strength(self, key: &KeyMaterial) -> Result<()>;
+  /// `modulus_bits` is only for RSA
+  fn generate_key_pair(self, modulus_bits: Option<usize>) -> Result<KeyPair>;
+  /// As long as the output of the hash, RFC 7518 section 3.2
+  fn generate_secret(self) -> Result<Vec<u8>>;
 }
 
 impl Key for Algorithm {
-  fn get_encoding_key(self, key: &[u8]) -> EncodingKey {
-    match self {
-      Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => EncodingKey::from_secret(key),
-      Algorithm::RS256
-      | Algorithm::RS384
-      | Algorithm::RS512
-      | Algorithm::PS256
-      | Algorithm::PS384
-      | Algorithm::PS512 => EncodingKey::from_rsa_pem(key).unwrap(),
-      Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(key).unwrap(),
+  fn get_encoding_key(self, key: &KeyMaterial) -> Result<EncodingKey> {
+    if let KeyMaterial::Jwk(jwk) = key {
+      jwk.check_usage(self, KeyOperation::Sign)?;
+    }
+
+    check_curve(self, key)?;
+
+    let key = match (self.family(), self) {
+      (AlgorithmFamily::Hmac, _) => {
+        EncodingKey::Jwt(jsonwebtoken::EncodingKey::from_secret(&key.secret()?))
+      }
+      (AlgorithmFamily::Rsa, _) => rsa::encoding_key(key)?,
+      (AlgorithmFamily::Ec, Algorithm::ES512) => EncodingKey::P521(ec::p521_signing_key(key)?),
+      (AlgorithmFamily::Ec, Algorithm::ES256K) => EncodingKey::K256(ec::k256_signing_key(key)?),
+      (AlgorithmFamily::Ec, _) => EncodingKey::Jwt(ec::encoding_key(self, key)?),
+      (AlgorithmFamily::Okp, _) => EncodingKey::Ed25519(eddsa::signing_key(key)?),
+      // a key means the token should be signed
+      (AlgorithmFamily::Unsecured, _) => bail!(ErrorKind::InvalidAlgorithm),
+    };
+
+    Ok(key)
+  }
+
+  fn get_decoding_key(self, key: &KeyMaterial) -> Result<DecodingKey> {
+    if let KeyMaterial::Jwk(jwk) = key {
+      jwk.check_usage(self, KeyOperation::Verify)?;
+    }
+
+    check_curve(self, key)?;
+
+    let key = match (self.family(), self) {
+      (AlgorithmFamily::Hmac, _) => {
+        DecodingKey::Jwt(jsonwebtoken::DecodingKey::from_secret(&key.secret()?).into_static())
+      }
+      (AlgorithmFamily::Rsa, _) => rsa::decoding_key(key)?,
+      (AlgorithmFamily::Ec, Algorithm::ES512) => DecodingKey::P521(ec::p521_verifying_key(key)?),
+      (AlgorithmFamily::Ec, Algorithm::ES256K) => DecodingKey::K256(ec::k256_verifying_key(key)?),
+      (AlgorithmFamily::Ec, _) => DecodingKey::Jwt(ec::decoding_key(self, key)?),
+      (AlgorithmFamily::Okp, _) => DecodingKey::Ed25519(eddsa::verifying_key(key)?),
+      // a key means the token should be signed
+      (AlgorithmFamily::Unsecured, _) => bail!(ErrorKind::SignatureRequired),
+    };
+
+    Ok(key)
+  }
+
+  fn check_key_strength(self, key: &KeyMaterial) -> Result<()> {
+    match self.family() {
+      AlgorithmFamily::Hmac => {
+        let min_bytes = match self {
+          Algorithm::HS384 => 48,
+          Algorithm::HS512 => 64,
+          _ => 32,
+        };
+        let bytes = key.secret()?.len();
+        if bytes < min_bytes {
+          let reason = format!("a secret of at least {} bytes, got {}", min_bytes, bytes);
+          bail!(ErrorKind::InsecureKey(self.as_str(), reason));
+        }
+      }
+      AlgorithmFamily::Rsa => {
+        let bits = rsa::public_key(key)?.n().bits();
+        if bits < rsa::MIN_MODULUS_BITS {
+          let reason = format!(
+            "a modulus of at least {} bits, got {}",
+            rsa::MIN_MODULUS_BITS,
+            bits
+          );
+          bail!(ErrorKind::InsecureKey(self.as_str(), reason));
+        }
+      }
+      _ => {}
     }
+
+    Ok(())
   }
 
-  fn get_decoding_key(self, key: &[u8]) -> DecodingKey {
+  fn generate_key_pair(self, modulus_bits: Option<usize>) -> Result<KeyPair> {
+    if modulus_bits.is_some() && self.family() != AlgorithmFamily::Rsa {
+      bail!(ErrorKind::InvalidOption(
+        "modulusLength",
+        "is only for RSA".to_string()
+      ));
+    }
+
     match self {
-      Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => DecodingKey::from_secret(key),
-      Algorithm::RS256
-      | Algorithm::RS384
-      | Algorithm::RS512
-      | Algorithm::PS256
-      | Algorithm::PS384
-      | Algorithm::PS512 => DecodingKey::from_rsa_pem(key).unwrap(),
-      Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(key).unwrap(),
+      Algorithm::ES256 => ec::generate_key_pair::<p256::NistP256>(self, PublicKey::P256),
+      Algorithm::ES384 => ec::generate_key_pair::<p384::NistP384>(self, PublicKey::P384),
+      Algorithm::ES512 => ec::generate_key_pair::<p521::NistP521>(self, PublicKey::P521),
+      Algorithm::ES256K => ec::generate_key_pair::<k256::Secp256k1>(self, PublicKey::K256),
+      Algorithm::EdDSA => eddsa::generate_key_pair(self),
+      _ if self.family() == AlgorithmFamily::Rsa => {
+        rsa::generate_key_pair(self, modulus_bits.unwrap_or(2048))
+      }
+      Algorithm::None => bail!(ErrorKind::InvalidArgument("alg", UNSECURED_TAKES_NO_KEY)),
+      _ => bail!(ErrorKind::InvalidArgument(
+        "alg",
+        "must be an asymmetric algorithm, generateSecret makes HMAC keys"
+      )),
+    }
+  }
+
+  fn generate_secret(self) -> Result<Vec<u8>> {
+    let len = match self {
+      Algorithm::HS256 => 32,
+      Algorithm::HS384 => 48,
+      Algorithm::HS512 => 64,
+      Algorithm::None => bail!(ErrorKind::InvalidArgument("alg", UNSECURED_TAKES_NO_KEY)),
+      _ => bail!(ErrorKind::InvalidArgument(
+        "alg",
+        "must be an HMAC algorithm, generateKeyPair makes asymmetric keys"
+      )),
+    };
+
+    let mut secret = vec![0; len];
+    OsRng.fill_bytes(&mut secret);
+    Ok(secret)
+  }
+}
+
+const UNSECURED_TAKES_NO_KEY: &str = "is \"none\", for unsecured tokens, which take no key";
+
+/// Checks an ECDSA key is on the curve of `alg`, a PEM of another curve would only fail to verify
+fn check_curve(alg: Algorithm, key: &KeyMaterial) -> Result<()> {
+  let expected = match alg.curve() {
+    Some("P-256") => "a P-256",
+    Some("P-384") => "a P-384",
+    Some("P-521") => "a P-521",
+    Some("secp256k1") => "a secp256k1",
+    _ => return Ok(()),
+  };
+  let crv = match key {
+    KeyMaterial::Jwk(jwk) => jwk.crv.as_deref(),
+    // a key that is no EC key is left for the parsing to reject
+    KeyMaterial::Bytes(_) => PublicKey::new(key)
+      .ok()
+      .and_then(|public_key| public_key.curve()),
+  };
+
+  match crv {
+    Some(crv) if Some(crv) != alg.curve() => {
+      bail!(ErrorKind::InvalidKeyType(expected, alg.as_str()))
     }
+    _ => Ok(()),
   }
 }
@@ -0,0 +1,102 @@
+use crate::errors::{ErrorKind, Result};
+use crate::jsonwebtoken_mod::algorithm::Algorithm;
+use crate::jsonwebtoken_mod::rsa;
+use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+use p521::ecdsa::signature::{Signer, Verifier};
+
+/// A parsed private key or secret
+pub enum EncodingKey {
+  /// for the algorithms `jsonwebtoken` implements
+  Jwt(jsonwebtoken::EncodingKey),
+  P521(p521::ecdsa::SigningKey),
+  K256(k256::ecdsa::SigningKey),
+  /// a modulus under 2048 bits, which ring refuses
+  Rsa(::rsa::RsaPrivateKey),
+  Ed25519(SigningKey),
+  /// no key, for `alg: none`
+  Unsecured,
+}
+
+/// A parsed public key or secret
+pub enum DecodingKey {
+  /// for the algorithms `jsonwebtoken` implements
+  Jwt(jsonwebtoken::DecodingKey<'static>),
+  P521(p521::ecdsa::VerifyingKey),
+  K256(k256::ecdsa::VerifyingKey),
+  /// a modulus under 2048 bits, which ring refuses
+  Rsa(::rsa::RsaPublicKey),
+  Ed25519(VerifyingKey),
+  /// no key, for `alg: none`
+  Unsecured,
+}
+
+/// Signs `message`, giving the base64url signature segment of a token
+pub fn sign(message: &str, key: &EncodingKey, alg: Algorithm) -> Result<String> {
+  match (key, alg) {
+    (EncodingKey::Jwt(key), alg) => {
+      let alg = alg.to_jsonwebtoken().ok_or(ErrorKind::InvalidAlgorithm)?;
+      Ok(jsonwebtoken::crypto::sign(message, key, alg)?)
+    }
+    (EncodingKey::P521(key), Algorithm::ES512) => {
+      let signature: p521::ecdsa::Signature = key.sign(message.as_bytes());
+      Ok(b64_encode(&signature.to_bytes()))
+    }
+    (EncodingKey::K256(key), Algorithm::ES256K) => {
+      let signature: k256::ecdsa::Signature = key.sign(message.as_bytes());
+      Ok(b64_encode(&signature.to_bytes()))
+    }
+    (EncodingKey::Rsa(key), alg) => Ok(b64_encode(&rsa::sign(key, alg, message.as_bytes())?)),
+    (EncodingKey::Ed25519(key), Algorithm::EdDSA) => {
+      Ok(b64_encode(&key.sign(message.as_bytes()).to_bytes()))
+    }
+    (EncodingKey::Unsecured, Algorithm::None) => Ok(String::new()),
+    _ => bail!(ErrorKind::InvalidAlgorithm),
+  }
+}
+
+/// Checks the base64url `signature` segment of a token against `message`
+pub fn verify(signature: &str, message: &str, key: &DecodingKey, alg: Algorithm) -> Result<bool> {
+  match (key, alg) {
+    (DecodingKey::Jwt(key), alg) => {
+      let alg = alg.to_jsonwebtoken().ok_or(ErrorKind::InvalidAlgorithm)?;
+      Ok(jsonwebtoken::crypto::verify(signature, message, key, alg)?)
+    }
+    (DecodingKey::P521(key), Algorithm::ES512) => {
+      let signature = b64_decode(signature)?;
+      Ok(
+        p521::ecdsa::Signature::from_slice(&signature)
+          .is_ok_and(|signature| key.verify(message.as_bytes(), &signature).is_ok()),
+      )
+    }
+    (DecodingKey::K256(key), Algorithm::ES256K) => {
+      let signature = b64_decode(signature)?;
+      // k256 only takes the low-S form it signs with, RFC 8812 allows both
+      Ok(
+        k256::ecdsa::Signature::from_slice(&signature).is_ok_and(|signature| {
+          let signature = signature.normalize_s().unwrap_or(signature);
+          key.verify(message.as_bytes(), &signature).is_ok()
+        }),
+      )
+    }
+    (DecodingKey::Rsa(key), alg) => {
+      rsa::verify(key, alg, message.as_bytes(), &b64_decode(signature)?)
+    }
+    (DecodingKey::Ed25519(key), Algorithm::EdDSA) => {
+      let signature = b64_decode(signature)?;
+      Ok(
+        Signature::from_slice(&signature)
+          .is_ok_and(|signature| key.verify_strict(message.as_bytes(), &signature).is_ok()),
+      )
+    }
+    (DecodingKey::Unsecured, Algorithm::None) => Ok(signature.is_empty()),
+    _ => bail!(ErrorKind::InvalidAlgorithm),
+  }
+}
+
+pub fn b64_encode(input: &[u8]) -> String {
+  base64::encode_config(input, base64::URL_SAFE_NO_PAD)
+}
+
+pub fn b64_decode(input: &str) -> Result<Vec<u8>> {
+  base64::decode_config(input, base64::URL_SAFE_NO_PAD).map_err(|_| ErrorKind::InvalidToken.into())
+}
@@ -0,0 +1,712 @@
+use crate::errors::{ErrorKind, Result};
+use crate::jsonwebtoken_mod::algorithm::{Algorithm, AlgorithmFamily, AsStr, Key};
+use crate::jsonwebtoken_mod::crypto::{self, b64_decode, DecodingKey};
+use crate::jsonwebtoken_mod::header::JoseHeader;
+use crate::jsonwebtoken_mod::jwk::JwkSet;
+use crate::jsonwebtoken_mod::key_material::{is_pem, KeyMaterial, KeySource};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// The header and claims of a token
+#[derive(Debug)]
+pub struct TokenData<T> {
+  pub header: JoseHeader,
+  pub claims: T,
+}
+
+/// Like `jsonwebtoken::decode`, but derives the key from the token's own `alg`,
+/// so one allow-list can mix algorithm families.
+/// The claims are not validated, see `validation::ClaimValidation`
+/// `key` is `None` for an unsecured token, which `algorithms` must allow with `none`
+pub fn decode<T: DeserializeOwned>(
+  token: &str,
+  key: Option<&KeySource>,
+  algorithms: &[Algorithm],
+  allow_insecure_key_sizes: bool,
+) -> Result<TokenData<T>> {
+  check_raw_key_algorithms(key, algorithms)?;
+  let parts = Parts::split(token)?;
+  let header = parts.header()?;
+  if !algorithms.contains(&header.alg) {
+    bail!(ErrorKind::InvalidAlgorithm);
+  }
+
+  let key = decoding_key(
+    header.alg,
+    header.kid.as_deref(),
+    key,
+    allow_insecure_key_sizes,
+  )?;
+  parts.verify(header, &key)
+}
+
+/// The header of a token, without checking the signature
+pub fn decode_header(token: &str) -> Result<JoseHeader> {
+  Parts::split(token)?.header()
+}
+
+/// The header and claims of a token, without checking the signature
+pub fn dangerous_insecure_decode<T: DeserializeOwned>(
+  token: &str,
+) -> Result<(Map<String, Value>, T)> {
+  let parts = Parts::split(token)?;
+  Ok((decode_part(parts.header)?, decode_part(parts.claims)?))
+}
+
+/// A key parsed once for every allowed algorithm, for tokens verified over and over
+pub struct DecodingKeys {
+  key: Option<KeySource>,
+  algorithms: Vec<Algorithm>,
+  /// by algorithm and by the index of the key in a JWK Set, 0 for a single key
+  keys: HashMap<(Algorithm, usize), DecodingKey>,
+  allow_insecure_key_sizes: bool,
+}
+
+impl DecodingKeys {
+  pub fn new(
+    key: Option<KeySource>,
+    algorithms: &[Algorithm],
+    allow_insecure_key_sizes: bool,
+  ) -> Result<Self> {
+    check_raw_key_algorithms(key.as_ref(), algorithms)?;
+    let mut keys = HashMap::new();
+    for &alg in algorithms {
+      match &key {
+        Some(KeySource::Set(set)) => {
+          for (index, jwk) in set.keys.iter().enumerate() {
+            if !jwk.fits(alg) {
+              continue;
+            }
+            let jwk = KeyMaterial::Jwk(Box::new(jwk.clone()));
+            if let Ok(decoding_key) = parse_key(alg, &jwk, allow_insecure_key_sizes) {
+              keys.insert((alg, index), decoding_key);
+            }
+          }
+        }
+        // the set changes, its keys are picked and parsed for each token
+        Some(KeySource::Remote(_)) => {}
+        _ => {
+          if let Ok(decoding_key) = decoding_key(alg, None, key.as_ref(), allow_insecure_key_sizes)
+          {
+            keys.insert((alg, 0), decoding_key);
+          }
+        }
+      }
+    }
+
+    // fail early when the key fits none of the algorithms
+    match (&key, algorithms.first()) {
+      (Some(KeySource::Remote(_)), _) => {}
+      (Some(KeySource::Set(_)), Some(_)) if keys.is_empty() => bail!(ErrorKind::NoMatchingKey),
+      (_, Some(&alg)) if keys.is_empty() => {
+        decoding_key(alg, None, key.as_ref(), allow_insecure_key_sizes)?;
+      }
+      _ => {}
+    }
+
+    Ok(DecodingKeys {
+      key,
+      algorithms: algorithms.to_vec(),
+      keys,
+      allow_insecure_key_sizes,
+    })
+  }
+
+  /// Like `decode`, without parsing the key again
+  pub fn decode<T: DeserializeOwned>(&self, token: &str) -> Result<TokenData<T>> {
+    let parts = Parts::split(token)?;
+    let header = parts.header()?;
+    if !self.algorithms.contains(&header.alg) {
+      bail!(ErrorKind::InvalidAlgorithm);
+    }
+
+    let index = match &self.key {
+      Some(KeySource::Set(set)) if header.alg != Algorithm::None => {
+        Some(set.find(header.kid.as_deref(), header.alg)?)
+      }
+      Some(KeySource::Remote(_)) => None,
+      _ => Some(0),
+    };
+    match index.and_then(|index| self.keys.get(&(header.alg, index))) {
+      Some(key) => parts.verify(header, key),
+      // the key does not fit this algorithm, parse it again for the error
+      None => {
+        let key = decoding_key(
+          header.alg,
+          header.kid.as_deref(),
+          self.key.as_ref(),
+          self.allow_insecure_key_sizes,
+        )?;
+        parts.verify(header, &key)
+      }
+    }
+  }
+}
+
+/// The `header.claims.signature` segments of a token
+struct Parts<'a> {
+  header: &'a str,
+  claims: &'a str,
+  signature: &'a str,
+}
+
+impl<'a> Parts<'a> {
+  fn split(token: &'a str) -> Result<Self> {
+    let mut parts = token.split('.');
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+      (Some(header), Some(claims), Some(signature), None) => Ok(Parts {
+        header,
+        claims,
+        signature,
+      }),
+      _ => bail!(ErrorKind::Malformed),
+    }
+  }
+
+  fn header(&self) -> Result<JoseHeader> {
+    let header: Map<String, Value> = decode_part(self.header)?;
+    // an `alg` this crate does not know is not allowed, rather than a broken token
+    match header
+      .get("alg")
+      .cloned()
+      .map(serde_json::from_value::<Algorithm>)
+    {
+      // a member of the wrong type, like a numeric `kid`, is a broken token too
+      Some(Ok(_)) => {
+        serde_json::from_value(Value::Object(header)).map_err(|_| ErrorKind::InvalidToken.into())
+      }
+      Some(Err(_)) => bail!(ErrorKind::InvalidAlgorithm),
+      None => bail!(ErrorKind::InvalidToken),
+    }
+  }
+
+  fn verify<T: DeserializeOwned>(
+    &self,
+    header: JoseHeader,
+    key: &DecodingKey,
+  ) -> Result<TokenData<T>> {
+    let message = &self.signature_input();
+    if !crypto::verify(self.signature, message, key, header.alg)? {
+      bail!(ErrorKind::InvalidSignature);
+    }
+
+    Ok(TokenData {
+      header,
+      claims: decode_part(self.claims)?,
+    })
+  }
+
+  fn signature_input(&self) -> String {
+    [self.header, self.claims].join(".")
+  }
+}
+
+fn decode_part<T: DeserializeOwned>(part: &str) -> Result<T> {
+  serde_json::from_slice(&b64_decode(part)?).map_err(|_| ErrorKind::InvalidToken.into())
+}
+
+/// Parses `key` for `alg`, then checks it is strong enough unless `allow_insecure_key_sizes`
+fn parse_key(
+  alg: Algorithm,
+  key: &KeyMaterial,
+  allow_insecure_key_sizes: bool,
+) -> Result<DecodingKey> {
+  let decoding_key = alg.get_decoding_key(key)?;
+  if !allow_insecure_key_sizes {
+    alg.check_key_strength(key)?;
+  }
+  Ok(decoding_key)
+}
+
+fn set_decoding_key(
+  set: &JwkSet,
+  kid: Option<&str>,
+  alg: Algorithm,
+  allow_insecure_key_sizes: bool,
+) -> Result<DecodingKey> {
+  let jwk = set.keys[set.find(kid, alg)?].clone();
+  parse_key(
+    alg,
+    &KeyMaterial::Jwk(Box::new(jwk)),
+    allow_insecure_key_sizes,
+  )
+}
+
+/// Raw bytes are an HMAC secret and an Ed25519 key alike, so an allow-list with both
+/// would let whoever has the public key forge HMAC tokens with it
+fn check_raw_key_algorithms(key: Option<&KeySource>, algorithms: &[Algorithm]) -> Result<()> {
+  let raw = matches!(key, Some(KeySource::Key(KeyMaterial::Bytes(bytes))) if !is_pem(bytes));
+  let allows = |family| algorithms.iter().any(|alg| alg.family() == family);
+  if raw && allows(AlgorithmFamily::Hmac) && allows(AlgorithmFamily::Okp) {
+    bail!(ErrorKind::InvalidOption(
+      "algorithms",
+      "can not mix HMAC algorithms with EdDSA for a key given as raw bytes".to_string()
+    ));
+  }
+  Ok(())
+}
+
+/// The key for `alg`, picked from a JWK Set by `kid`
+fn decoding_key(
+  alg: Algorithm,
+  kid: Option<&str>,
+  key: Option<&KeySource>,
+  allow_insecure_key_sizes: bool,
+) -> Result<DecodingKey> {
+  let key = match (alg, key) {
+    (Algorithm::None, None) => return Ok(DecodingKey::Unsecured),
+    (Algorithm::None, Some(_)) => bail!(ErrorKind::SignatureRequired),
+    (_, Some(KeySource::Key(key))) => key,
+    (_, Some(KeySource::Set(set))) => {
+      return set_decoding_key(set, kid, alg, allow_insecure_key_sizes)
+    }
+    (_, Some(KeySource::Remote(remote))) => {
+      let set = remote.key_set(kid, alg)?;
+      return set_decoding_key(&set, kid, alg, allow_insecure_key_sizes);
+    }
+    (_, None) => bail!(ErrorKind::MissingKey),
+  };
+
+  // a public key is no secret: whoever has it could forge HMAC tokens
+  match (alg.family(), key) {
+    (AlgorithmFamily::Hmac, KeyMaterial::Bytes(bytes)) if is_pem(bytes) => {
+      bail!(ErrorKind::InvalidKeyType("a symmetric", alg.as_str()))
+    }
+    (AlgorithmFamily::Rsa, KeyMaterial::Bytes(bytes))
+    | (AlgorithmFamily::Ec, KeyMaterial::Bytes(bytes))
+      if !is_pem(bytes) =>
+    {
+      bail!(ErrorKind::InvalidKeyType("an asymmetric", alg.as_str()))
+    }
+    _ => parse_key(alg, key, allow_insecure_key_sizes),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::jsonwebtoken_mod::encoding::encode;
+  use crate::jsonwebtoken_mod::jwk::Jwk;
+  use ::rsa::pkcs1::EncodeRsaPublicKey;
+  use p521::elliptic_curve::sec1::ToEncodedPoint;
+  use pkcs8::{EncodePrivateKey, EncodePublicKey, LineEnding};
+  use serde_json::json;
+
+  const PUBLIC_KEY: &[u8] = b"-----BEGIN PUBLIC KEY-----\nMFkw\n-----END PUBLIC KEY-----\n";
+  const ED25519_SEED: [u8; 32] = [7; 32];
+  /// at least as long as the output of SHA-512, for HS512
+  const SECRET: &[u8] = b"a secret long enough for every HMAC algorithm, HS512 included....";
+
+  fn secret() -> KeyMaterial {
+    KeyMaterial::Bytes(SECRET.to_vec())
+  }
+
+  fn token_with_key(alg: Algorithm, key: &KeyMaterial) -> String {
+    let header: JoseHeader = serde_json::from_value(json!({ "alg": alg })).unwrap();
+    let key = alg.get_encoding_key(key).unwrap();
+    encode(&header, &json!({ "foo": "bar" }), &key).unwrap()
+  }
+
+  fn token(alg: Algorithm) -> String {
+    token_with_key(alg, &secret())
+  }
+
+  fn kind<T>(result: Result<T>) -> ErrorKind {
+    result.map(|_| ()).map_err(|e| e.0).unwrap_err()
+  }
+
+  #[test]
+  fn test_decode_with_mixed_algorithms() {
+    let algorithms = [Algorithm::RS256, Algorithm::ES256, Algorithm::HS384];
+    let data: TokenData<Value> = decode(
+      &token(Algorithm::HS384),
+      Some(&KeySource::Key(secret())),
+      &algorithms,
+      false,
+    )
+    .unwrap();
+    assert_eq!(data.header.alg, Algorithm::HS384);
+    assert_eq!(data.claims, json!({ "foo": "bar" }));
+  }
+
+  #[test]
+  fn test_decode_rejects_algorithm_not_allowed() {
+    let result = decode::<Value>(
+      &token(Algorithm::HS512),
+      Some(&KeySource::Key(secret())),
+      &[Algorithm::HS256],
+      false,
+    );
+    assert!(matches!(kind(result), ErrorKind::InvalidAlgorithm));
+
+    let header = crypto::b64_encode(br#"{"alg":"XS256"}"#);
+    let token = format!("{}.e30.c2ln", header);
+    let result = decode::<Value>(
+      &token,
+      Some(&KeySource::Key(secret())),
+      &[Algorithm::HS256],
+      false,
+    );
+    assert!(matches!(kind(result), ErrorKind::InvalidAlgorithm));
+  }
+
+  #[test]
+  fn test_decode_malformed_tokens() {
+    for token in &["", "a.b", "a.b.c.d"] {
+      let result = decode::<Value>(
+        token,
+        Some(&KeySource::Key(secret())),
+        &[Algorithm::HS256],
+        false,
+      );
+      assert!(matches!(kind(result), ErrorKind::Malformed), "{}", token);
+    }
+    let result = decode::<Value>(
+      "e30.e30.",
+      Some(&KeySource::Key(secret())),
+      &[Algorithm::HS256],
+      false,
+    );
+    assert!(matches!(kind(result), ErrorKind::InvalidToken));
+  }
+
+  #[test]
+  fn test_decode_rejects_public_key_as_secret() {
+    let algorithms = [Algorithm::RS256, Algorithm::HS256];
+    let key = KeySource::Key(KeyMaterial::Bytes(PUBLIC_KEY.to_vec()));
+    let result = decode::<Value>(&token(Algorithm::HS256), Some(&key), &algorithms, false);
+    assert!(matches!(
+      kind(result),
+      ErrorKind::InvalidKeyType("a symmetric", "HS256")
+    ));
+  }
+
+  #[test]
+  fn test_decode_rejects_secret_as_public_key() {
+    let header = crypto::b64_encode(br#"{"alg":"RS256"}"#);
+    let token = token(Algorithm::HS256);
+    let (_, rest) = token.split_once('.').unwrap();
+    let token = format!("{}.{}", header, rest);
+    let result = decode::<Value>(
+      &token,
+      Some(&KeySource::Key(secret())),
+      &[Algorithm::RS256],
+      false,
+    );
+    assert!(matches!(
+      kind(result),
+      ErrorKind::InvalidKeyType("an asymmetric", "RS256")
+    ));
+  }
+
+  #[test]
+  fn test_decoding_keys() {
+    let keys = DecodingKeys::new(
+      Some(KeySource::Key(secret())),
+      &[Algorithm::RS256, Algorithm::HS256],
+      false,
+    )
+    .unwrap();
+    let data: TokenData<Value> = keys.decode(&token(Algorithm::HS256)).unwrap();
+    assert_eq!(data.claims, json!({ "foo": "bar" }));
+    let result = keys.decode::<Value>(&token(Algorithm::HS512));
+    assert!(matches!(kind(result), ErrorKind::InvalidAlgorithm));
+
+    let result = DecodingKeys::new(
+      Some(KeySource::Key(KeyMaterial::Bytes(PUBLIC_KEY.to_vec()))),
+      &[Algorithm::HS256],
+      false,
+    );
+    assert!(matches!(
+      kind(result),
+      ErrorKind::InvalidKeyType("a symmetric", "HS256")
+    ));
+  }
+
+  #[test]
+  fn test_eddsa_with_raw_keys() {
+    let signing_key = KeyMaterial::Bytes(ED25519_SEED.to_vec());
+    let public_key = ed25519_dalek::SigningKey::from_bytes(&ED25519_SEED).verifying_key();
+    let verifying_key = KeySource::Key(KeyMaterial::Bytes(public_key.to_bytes().to_vec()));
+
+    let token = token_with_key(Algorithm::EdDSA, &signing_key);
+    let data: TokenData<Value> =
+      decode(&token, Some(&verifying_key), &[Algorithm::EdDSA], false).unwrap();
+    assert_eq!(data.header.alg, Algorithm::EdDSA);
+
+    let tampered = token.replace(".eyJ", ".eyK");
+    let result = decode::<Value>(&tampered, Some(&verifying_key), &[Algorithm::EdDSA], false);
+    assert!(result.is_err());
+    let other_key = ed25519_dalek::SigningKey::from_bytes(&[8; 32]).verifying_key();
+    let other_key = KeySource::Key(KeyMaterial::Bytes(other_key.to_bytes().to_vec()));
+    let result = decode::<Value>(&token, Some(&other_key), &[Algorithm::EdDSA], false);
+    assert!(matches!(kind(result), ErrorKind::InvalidSignature));
+  }
+
+  #[test]
+  fn test_decode_rejects_raw_eddsa_public_key_as_secret() {
+    let public_key = ed25519_dalek::SigningKey::from_bytes(&ED25519_SEED).verifying_key();
+    let public_key = KeyMaterial::Bytes(public_key.to_bytes().to_vec());
+    // an HS256 token forged with the public key bytes as the secret
+    let forged = token_with_key(Algorithm::HS256, &public_key);
+    let algorithms = [Algorithm::EdDSA, Algorithm::HS256];
+    let key = KeySource::Key(public_key);
+
+    let result = decode::<Value>(&forged, Some(&key), &algorithms, false);
+    assert!(matches!(
+      kind(result),
+      ErrorKind::InvalidOption("algorithms", _)
+    ));
+    let result = DecodingKeys::new(Some(key.clone()), &algorithms, false);
+    assert!(matches!(
+      kind(result),
+      ErrorKind::InvalidOption("algorithms", _)
+    ));
+
+    // the same key verifies EdDSA tokens, and is a secret when `EdDSA` is not allowed
+    let token = token_with_key(Algorithm::EdDSA, &KeyMaterial::Bytes(ED25519_SEED.to_vec()));
+    assert!(decode::<Value>(&token, Some(&key), &[Algorithm::EdDSA], false).is_ok());
+    assert!(decode::<Value>(&forged, Some(&key), &[Algorithm::HS256], false).is_ok());
+  }
+
+  #[test]
+  fn test_raw_secrets_are_checked_the_same_every_time() {
+    let mixed = [Algorithm::HS256, Algorithm::EdDSA];
+    for _ in 0..200 {
+      let secret = KeyMaterial::Bytes(Algorithm::HS256.generate_secret().unwrap());
+      let token = token_with_key(Algorithm::HS256, &secret);
+      let key = KeySource::Key(secret);
+
+      // about half of all 32 bytes are an Ed25519 point too, that must not matter
+      assert!(decode::<Value>(&token, Some(&key), &[Algorithm::HS256], false).is_ok());
+      let keys = DecodingKeys::new(
+        Some(key.clone()),
+        &[Algorithm::HS256, Algorithm::HS384],
+        false,
+      );
+      assert!(keys.unwrap().decode::<Value>(&token).is_ok());
+      let result = decode::<Value>(&token, Some(&key), &mixed, false);
+      assert!(matches!(
+        kind(result),
+        ErrorKind::InvalidOption("algorithms", _)
+      ));
+    }
+  }
+
+  #[test]
+  fn test_decode_rejects_badly_typed_header_members() {
+    for header in [
+      json!({"alg": "HS256", "kid": 7}),
+      json!({"alg": "HS256", "typ": ["JWT"]}),
+    ] {
+      let header = crypto::b64_encode(header.to_string().as_bytes());
+      let token = token(Algorithm::HS256);
+      let (_, rest) = token.split_once('.').unwrap();
+      let token = format!("{}.{}", header, rest);
+
+      let error = decode::<Value>(
+        &token,
+        Some(&KeySource::Key(secret())),
+        &[Algorithm::HS256],
+        false,
+      )
+      .unwrap_err();
+      assert!(matches!(error.kind(), ErrorKind::InvalidToken));
+      assert_eq!(error.name(), "JsonWebTokenError");
+    }
+  }
+
+  #[test]
+  fn test_ec_jwks() {
+    let p256_point = p256::SecretKey::from_slice(&[3; 32])
+      .unwrap()
+      .public_key()
+      .to_encoded_point(false);
+    let p384_point = p384::SecretKey::from_slice(&[5; 48])
+      .unwrap()
+      .public_key()
+      .to_encoded_point(false);
+    let p521_point = p521::SecretKey::from_slice(&[1; 66])
+      .unwrap()
+      .public_key()
+      .to_encoded_point(false);
+    let k256_point = k256::SecretKey::from_slice(&[7; 32])
+      .unwrap()
+      .public_key()
+      .to_encoded_point(false);
+    let cases = [
+      (
+        Algorithm::ES256,
+        "P-256",
+        &[3; 32][..],
+        p256_point.as_bytes(),
+      ),
+      (
+        Algorithm::ES384,
+        "P-384",
+        &[5; 48][..],
+        p384_point.as_bytes(),
+      ),
+      (
+        Algorithm::ES512,
+        "P-521",
+        &[1; 66][..],
+        p521_point.as_bytes(),
+      ),
+      (
+        Algorithm::ES256K,
+        "secp256k1",
+        &[7; 32][..],
+        k256_point.as_bytes(),
+      ),
+    ];
+
+    for &(alg, crv, d, point) in &cases {
+      let (x, y) = point[1..].split_at((point.len() - 1) / 2);
+      let public_key = Jwk {
+        kty: "EC".to_string(),
+        crv: Some(crv.to_string()),
+        x: Some(crypto::b64_encode(x)),
+        y: Some(crypto::b64_encode(y)),
+        ..Default::default()
+      };
+      let private_key = Jwk {
+        d: Some(crypto::b64_encode(d)),
+        ..public_key.clone()
+      };
+
+      let token = token_with_key(alg, &KeyMaterial::Jwk(Box::new(private_key)));
+      let data: TokenData<Value> = decode(
+        &token,
+        Some(&KeySource::Key(KeyMaterial::Jwk(Box::new(public_key)))),
+        &[alg],
+        false,
+      )
+      .unwrap();
+      assert_eq!(data.header.alg, alg);
+    }
+  }
+
+  #[test]
+  fn test_oct_jwk() {
+    let key = KeyMaterial::Jwk(Box::new(Jwk {
+      kty: "oct".to_string(),
+      k: Some(crypto::b64_encode(SECRET)),
+      ..Default::default()
+    }));
+
+    let data: TokenData<Value> = decode(
+      &token(Algorithm::HS384),
+      Some(&KeySource::Key(key.clone())),
+      &[Algorithm::HS384],
+      false,
+    )
+    .unwrap();
+    assert_eq!(data.header.alg, Algorithm::HS384);
+    let data: TokenData<Value> = decode(
+      &token_with_key(Algorithm::HS256, &key),
+      Some(&KeySource::Key(secret())),
+      &[Algorithm::HS256],
+      false,
+    )
+    .unwrap();
+    assert_eq!(data.header.alg, Algorithm::HS256);
+  }
+
+  #[test]
+  fn test_unsecured_tokens() {
+    let token = format!("{}.e30.", crypto::b64_encode(br#"{"alg":"none"}"#));
+    let algorithms = [Algorithm::HS256, Algorithm::None];
+
+    let data: TokenData<Value> = decode(&token, None, &algorithms, false).unwrap();
+    assert_eq!(data.header.alg, Algorithm::None);
+
+    let result = decode::<Value>(&token, None, &[Algorithm::HS256], false);
+    assert!(matches!(kind(result), ErrorKind::InvalidAlgorithm));
+    let result = decode::<Value>(&token, Some(&KeySource::Key(secret())), &algorithms, false);
+    assert!(matches!(kind(result), ErrorKind::SignatureRequired));
+    let result = decode::<Value>(&format!("{}c2ln", token), None, &algorithms, false);
+    assert!(matches!(kind(result), ErrorKind::InvalidSignature));
+    let result = decode::<Value>(
+      &super::tests::token(Algorithm::HS256),
+      None,
+      &algorithms,
+      false,
+    );
+    assert!(matches!(kind(result), ErrorKind::MissingKey));
+  }
+
+  #[test]
+  fn test_key_strength() {
+    let short_secret = KeySource::Key(KeyMaterial::Bytes(SECRET[..31].to_vec()));
+    let token = token_with_key(Algorithm::HS256, &KeyMaterial::Bytes(SECRET[..31].to_vec()));
+    let result = decode::<Value>(&token, Some(&short_secret), &[Algorithm::HS256], false);
+    assert!(matches!(kind(result), ErrorKind::InsecureKey("HS256", _)));
+    let result = DecodingKeys::new(Some(short_secret.clone()), &[Algorithm::HS256], false);
+    assert!(matches!(kind(result), ErrorKind::InsecureKey("HS256", _)));
+    let keys = DecodingKeys::new(Some(short_secret), &[Algorithm::HS256], true).unwrap();
+    assert!(keys.decode::<Value>(&token).is_ok());
+
+    let rsa_key = ::rsa::RsaPrivateKey::new(&mut rand_core::OsRng, 1024).unwrap();
+    let private_key = rsa_key.to_pkcs8_pem(LineEnding::LF).unwrap();
+    let token = token_with_key(
+      Algorithm::RS256,
+      &KeyMaterial::Bytes(private_key.as_bytes().to_vec()),
+    );
+    let public_key = rsa_key
+      .to_public_key()
+      .to_public_key_pem(LineEnding::LF)
+      .unwrap();
+    let public_key = KeySource::Key(KeyMaterial::Bytes(public_key.into_bytes()));
+    let result = decode::<Value>(&token, Some(&public_key), &[Algorithm::RS256], false);
+    assert!(matches!(kind(result), ErrorKind::InsecureKey("RS256", _)));
+    assert!(decode::<Value>(&token, Some(&public_key), &[Algorithm::RS256], true).is_ok());
+    let unreadable = KeyMaterial::Bytes(b"not an RSA key".to_vec());
+    assert!(matches!(
+      kind(Algorithm::RS256.check_key_strength(&unreadable)),
+      ErrorKind::InvalidKey
+    ));
+
+    let p384_key = p384::SecretKey::from_slice(&[5; 48]).unwrap().public_key();
+    let point = p384_key.to_encoded_point(false);
+    let (x, y) = point.as_bytes()[1..].split_at(48);
+    let p384_key = KeySource::Key(KeyMaterial::Jwk(Box::new(Jwk {
+      kty: "EC".to_string(),
+      crv: Some("P-384".to_string()),
+      x: Some(crypto::b64_encode(x)),
+      y: Some(crypto::b64_encode(y)),
+      ..Default::default()
+    })));
+    let header = crypto::b64_encode(br#"{"alg":"ES256"}"#);
+    let token = format!("{}.e30.c2ln", header);
+    let result = decode::<Value>(&token, Some(&p384_key), &[Algorithm::ES256], true);
+    assert!(matches!(
+      kind(result),
+      ErrorKind::InvalidKeyType("a P-256", "ES256")
+    ));
+  }
+
+  #[test]
+  fn test_rsa_public_keys_over_4096_bits() {
+    // any odd modulus reads as a key, the signature below just does not match it
+    let modulus = (::rsa::BigUint::from(1u8) << 6143) + 1u8;
+    let rsa_key = ::rsa::RsaPublicKey::new_with_max_size(modulus, 65537u32.into(), 6144).unwrap();
+    let spki = rsa_key.to_public_key_pem(LineEnding::LF).unwrap();
+    let pkcs1 = rsa_key.to_pkcs1_pem(LineEnding::LF).unwrap();
+    let header = crypto::b64_encode(br#"{"alg":"RS256"}"#);
+    let token = format!("{}.e30.{}", header, crypto::b64_encode(&[1; 768]));
+
+    for pem in [spki, pkcs1] {
+      let public_key = KeyMaterial::Bytes(pem.into_bytes());
+      assert!(Algorithm::RS256.check_key_strength(&public_key).is_ok());
+      let result = decode::<Value>(
+        &token,
+        Some(&KeySource::Key(public_key)),
+        &[Algorithm::RS256],
+        false,
+      );
+      assert!(matches!(kind(result), ErrorKind::InvalidSignature));
+    }
+  }
+}
@@ -0,0 +1,157 @@
+use crate::errors::{ErrorKind, Result};
+use crate::jsonwebtoken_mod::algorithm::Algorithm;
+use crate::jsonwebtoken_mod::crypto::b64_encode;
+use crate::jsonwebtoken_mod::jwk::Jwk;
+use crate::jsonwebtoken_mod::key_material::{pem, KeyMaterial};
+use crate::jsonwebtoken_mod::key_pair::KeyPair;
+use p521::elliptic_curve::pkcs8::{
+  AssociatedOid, DecodePrivateKey, DecodePublicKey, EncodePrivateKey, LineEnding,
+};
+use p521::elliptic_curve::sec1::{
+  FromEncodedPoint, ModulusSize, ToEncodedPoint, ValidatePublicKey,
+};
+use p521::elliptic_curve::{AffinePoint, CurveArithmetic, FieldBytesSize, PublicKey, SecretKey};
+
+/// A P-256 or P-384 private key from a PEM, or an EC JWK with `d`, for ES256 and ES384
+pub fn encoding_key(alg: Algorithm, key: &KeyMaterial) -> Result<jsonwebtoken::EncodingKey> {
+  if let KeyMaterial::Bytes(bytes) = key {
+    return Ok(jsonwebtoken::EncodingKey::from_ec_pem(bytes)?);
+  }
+
+  // ring reads PKCS#8
+  let der = match alg {
+    Algorithm::ES256 => secret_key::<p256::NistP256>(key, "P-256")?.to_pkcs8_der(),
+    Algorithm::ES384 => secret_key::<p384::NistP384>(key, "P-384")?.to_pkcs8_der(),
+    _ => bail!(ErrorKind::InvalidAlgorithm),
+  };
+  let der = der.map_err(|_| ErrorKind::InvalidKey)?;
+  Ok(jsonwebtoken::EncodingKey::from_ec_der(der.as_bytes()))
+}
+
+/// A P-256 or P-384 public key from a PEM, or an EC JWK, for ES256 and ES384
+pub fn decoding_key(
+  alg: Algorithm,
+  key: &KeyMaterial,
+) -> Result<jsonwebtoken::DecodingKey<'static>> {
+  if let KeyMaterial::Bytes(bytes) = key {
+    return Ok(jsonwebtoken::DecodingKey::from_ec_pem(bytes)?.into_static());
+  }
+
+  // ring reads the uncompressed point
+  let point = match alg {
+    Algorithm::ES256 => public_key::<p256::NistP256>(key, "P-256")?
+      .to_encoded_point(false)
+      .as_bytes()
+      .to_vec(),
+    Algorithm::ES384 => public_key::<p384::NistP384>(key, "P-384")?
+      .to_encoded_point(false)
+      .as_bytes()
+      .to_vec(),
+    _ => bail!(ErrorKind::InvalidAlgorithm),
+  };
+  Ok(jsonwebtoken::DecodingKey::from_ec_der(&point).into_static())
+}
+
+/// A P-521 private key, for ES512
+pub fn p521_signing_key(key: &KeyMaterial) -> Result<p521::ecdsa::SigningKey> {
+  let secret_key = secret_key::<p521::NistP521>(key, "P-521")?;
+  p521::ecdsa::SigningKey::from_bytes(&secret_key.to_bytes())
+    .map_err(|_| ErrorKind::InvalidKey.into())
+}
+
+/// A P-521 public key, for ES512
+pub fn p521_verifying_key(key: &KeyMaterial) -> Result<p521::ecdsa::VerifyingKey> {
+  let public_key = public_key::<p521::NistP521>(key, "P-521")?;
+  p521::ecdsa::VerifyingKey::from_affine(*public_key.as_affine())
+    .map_err(|_| ErrorKind::InvalidKey.into())
+}
+
+/// A secp256k1 private key, for ES256K
+pub fn k256_signing_key(key: &KeyMaterial) -> Result<k256::ecdsa::SigningKey> {
+  Ok(secret_key::<k256::Secp256k1>(key, "secp256k1")?.into())
+}
+
+/// A secp256k1 public key, for ES256K
+pub fn k256_verifying_key(key: &KeyMaterial) -> Result<k256::ecdsa::VerifyingKey> {
+  Ok(public_key::<k256::Secp256k1>(key, "secp256k1")?.into())
+}
+
+/// A new key pair on curve `C`, `public_key` tells the curve apart
+pub fn generate_key_pair<C>(
+  alg: Algorithm,
+  public_key: fn(PublicKey<C>) -> crate::jsonwebtoken_mod::public_key::PublicKey,
+) -> Result<KeyPair>
+where
+  C: CurveArithmetic + AssociatedOid + ValidatePublicKey,
+  AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+  FieldBytesSize<C>: ModulusSize,
+{
+  let secret_key = SecretKey::<C>::random(&mut rand_core::OsRng);
+  let pem = secret_key
+    .to_pkcs8_pem(LineEnding::LF)
+    .map_err(|_| ErrorKind::InvalidKey)?;
+  let private_members = Jwk {
+    d: Some(b64_encode(&secret_key.to_bytes())),
+    ..Jwk::default()
+  };
+
+  KeyPair::new(
+    alg,
+    pem.to_string(),
+    public_key(secret_key.public_key()),
+    private_members,
+  )
+}
+
+/// A private key on curve `C` from a PKCS#8 or SEC1 PEM, or an EC JWK with `d`
+fn secret_key<C>(key: &KeyMaterial, crv: &str) -> Result<SecretKey<C>>
+where
+  C: CurveArithmetic + AssociatedOid + ValidatePublicKey,
+  AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+  FieldBytesSize<C>: ModulusSize,
+{
+  let secret_key = match key {
+    KeyMaterial::Bytes(bytes) => {
+      let pem = pem(bytes)?;
+      SecretKey::from_pkcs8_pem(pem)
+        .or_else(|_| SecretKey::from_sec1_pem(pem))
+        .ok()
+    }
+    KeyMaterial::Jwk(jwk) => {
+      check_curve(jwk, crv)?;
+      SecretKey::from_slice(&Jwk::decode_member(&jwk.d)?).ok()
+    }
+  };
+
+  secret_key.ok_or_else(|| ErrorKind::InvalidKey.into())
+}
+
+/// A public key on curve `C` from a SPKI PEM, the PEM of its private key, or an EC JWK
+pub fn public_key<C>(key: &KeyMaterial, crv: &str) -> Result<PublicKey<C>>
+where
+  C: CurveArithmetic + AssociatedOid + ValidatePublicKey,
+  AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+  FieldBytesSize<C>: ModulusSize,
+{
+  match key {
+    KeyMaterial::Bytes(bytes) => match PublicKey::from_public_key_pem(pem(bytes)?) {
+      Ok(public_key) => Ok(public_key),
+      Err(_) => Ok(secret_key::<C>(key, crv)?.public_key()),
+    },
+    KeyMaterial::Jwk(jwk) => {
+      check_curve(jwk, crv)?;
+      // the uncompressed SEC1 encoding of the point
+      let mut point = vec![0x04];
+      point.extend(Jwk::decode_member(&jwk.x)?);
+      point.extend(Jwk::decode_member(&jwk.y)?);
+      PublicKey::from_sec1_bytes(&point).map_err(|_| ErrorKind::InvalidKey.into())
+    }
+  }
+}
+
+fn check_curve(jwk: &Jwk, crv: &str) -> Result<()> {
+  if jwk.kty != "EC" || jwk.crv.as_deref() != Some(crv) {
+    bail!(ErrorKind::InvalidKey);
+  }
+  Ok(())
+}
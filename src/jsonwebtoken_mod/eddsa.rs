@@ -0,0 +1,86 @@
+use crate::errors::{ErrorKind, Result};
+use crate::jsonwebtoken_mod::algorithm::Algorithm;
+use crate::jsonwebtoken_mod::crypto::b64_encode;
+use crate::jsonwebtoken_mod::jwk::Jwk;
+use crate::jsonwebtoken_mod::key_material::{is_pem, pem, KeyMaterial};
+use crate::jsonwebtoken_mod::key_pair::KeyPair;
+use crate::jsonwebtoken_mod::public_key::PublicKey;
+use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, KeypairBytes};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rand_core::{OsRng, RngCore};
+use std::convert::TryInto;
+
+/// An Ed25519 private key from a PKCS#8 PEM, the 32 bytes of its seed or an OKP JWK with `d`
+pub fn signing_key(key: &KeyMaterial) -> Result<SigningKey> {
+  match key {
+    KeyMaterial::Bytes(bytes) if is_pem(bytes) => {
+      SigningKey::from_pkcs8_pem(pem(bytes)?).map_err(|_| ErrorKind::InvalidKey.into())
+    }
+    KeyMaterial::Bytes(bytes) => Ok(SigningKey::from_bytes(&raw(bytes)?)),
+    KeyMaterial::Jwk(jwk) => {
+      check_curve(jwk)?;
+      Ok(SigningKey::from_bytes(&raw(&Jwk::decode_member(&jwk.d)?)?))
+    }
+  }
+}
+
+/// An Ed25519 public key from a SPKI or PKCS#8 PEM, its 32 bytes or an OKP JWK
+pub fn verifying_key(key: &KeyMaterial) -> Result<VerifyingKey> {
+  let key = match key {
+    KeyMaterial::Bytes(bytes) if is_pem(bytes) => {
+      let pem = pem(bytes)?;
+      VerifyingKey::from_public_key_pem(pem)
+        .or_else(|_| SigningKey::from_pkcs8_pem(pem).map(|key| key.verifying_key()))
+        .map_err(|_| ErrorKind::InvalidKey)?
+    }
+    KeyMaterial::Bytes(bytes) => {
+      VerifyingKey::from_bytes(&raw(bytes)?).map_err(|_| ErrorKind::InvalidKey)?
+    }
+    KeyMaterial::Jwk(jwk) => {
+      check_curve(jwk)?;
+      let x = raw(&Jwk::decode_member(&jwk.x)?)?;
+      VerifyingKey::from_bytes(&x).map_err(|_| ErrorKind::InvalidKey)?
+    }
+  };
+
+  Ok(key)
+}
+
+/// A new Ed25519 key pair
+pub fn generate_key_pair(alg: Algorithm) -> Result<KeyPair> {
+  let mut seed = [0; 32];
+  OsRng.fill_bytes(&mut seed);
+  let signing_key = SigningKey::from_bytes(&seed);
+  // PKCS#8 v1, OpenSSL does not read the public key of v2
+  let keypair_bytes = KeypairBytes {
+    secret_key: seed,
+    public_key: None,
+  };
+  let pem = keypair_bytes
+    .to_pkcs8_pem(LineEnding::LF)
+    .map_err(|_| ErrorKind::InvalidKey)?;
+  let private_members = Jwk {
+    d: Some(b64_encode(&seed)),
+    ..Jwk::default()
+  };
+
+  KeyPair::new(
+    alg,
+    pem.to_string(),
+    PublicKey::Ed25519(signing_key.verifying_key()),
+    private_members,
+  )
+}
+
+fn raw(bytes: &[u8]) -> Result<[u8; 32]> {
+  bytes.try_into().map_err(|_| ErrorKind::InvalidKey.into())
+}
+
+fn check_curve(jwk: &Jwk) -> Result<()> {
+  match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+    ("OKP", Some("Ed25519")) => Ok(()),
+    ("OKP", Some("Ed448")) => bail!(ErrorKind::UnsupportedKey("Ed448".to_string())),
+    _ => bail!(ErrorKind::InvalidKey),
+  }
+}
@@ -0,0 +1,19 @@
+use crate::errors::Result;
+use crate::jsonwebtoken_mod::crypto::{self, b64_encode, EncodingKey};
+use crate::jsonwebtoken_mod::header::JoseHeader;
+use serde::Serialize;
+
+/// Like `jsonwebtoken::encode`, but writes every member of the header
+pub fn encode<T: Serialize>(header: &JoseHeader, claims: &T, key: &EncodingKey) -> Result<String> {
+  let encoded_header = b64_encode_part(header)?;
+  let encoded_claims = b64_encode_part(claims)?;
+  let message = [encoded_header, encoded_claims].join(".");
+  let signature = crypto::sign(&message, key, header.alg)?;
+
+  Ok([message, signature].join("."))
+}
+
+fn b64_encode_part<T: Serialize>(input: &T) -> Result<String> {
+  let json = serde_json::to_string(input)?;
+  Ok(b64_encode(json.as_bytes()))
+}
@@ -1,30 +1,38 @@
-use crate::jsonwebtoken_mod::algorithm::AsStr;
+use crate::errors::OrThrow;
+use crate::jsonwebtoken_mod::algorithm::Algorithm;
+use crate::neon_serde;
 use neon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 pub trait ToObject {
-  fn to_object<'a>(self, cx: &mut FunctionContext<'a>) -> Handle<'a, JsObject>;
+  fn to_object<'a, C: Context<'a>>(&self, cx: &mut C) -> JsResult<'a, JsObject>;
 }
 
-impl ToObject for jsonwebtoken::Header {
-  fn to_object<'a>(self, cx: &mut FunctionContext<'a>) -> Handle<'a, JsObject> {
-    let header = cx.empty_object();
-    let set_optional = |cx: &mut FunctionContext, key: &str, value: Option<&String>| -> NeonResult<()> {
-      if value.is_some() {
-        let js_string = cx.string(value.unwrap());
-        header.set(cx, key, js_string).unwrap();
-      }
-      Ok(())
-    };
-
-    let alg = cx.string(self.alg.as_str());
-    header.set(cx, "alg", alg).unwrap();
-    set_optional(cx, "cty", self.cty.as_ref()).unwrap();
-    set_optional(cx, "jku", self.jku.as_ref()).unwrap();
-    set_optional(cx, "kid", self.kid.as_ref()).unwrap();
-    set_optional(cx, "typ", self.typ.as_ref()).unwrap();
-    set_optional(cx, "x5t", self.x5t.as_ref()).unwrap();
-    set_optional(cx, "x5u", self.x5u.as_ref()).unwrap();
-
-    header
+impl ToObject for JoseHeader {
+  fn to_object<'a, C: Context<'a>>(&self, cx: &mut C) -> JsResult<'a, JsObject> {
+    let header = neon_serde::to_value(cx, self).or_throw(cx)?;
+    header.downcast_or_throw(cx)
   }
 }
+
+/// A JOSE header that, unlike `jsonwebtoken::Header`, keeps members it does not know about
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoseHeader {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub typ: Option<String>,
+  pub alg: Algorithm,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub cty: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub jku: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub kid: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  /// a URI, or an array of them as some issuers send
+  pub x5u: Option<Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub x5t: Option<String>,
+  #[serde(flatten)]
+  pub extra: Map<String, Value>,
+}
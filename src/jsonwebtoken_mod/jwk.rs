@@ -0,0 +1,316 @@
+use crate::errors::{ErrorKind, Result};
+use crate::jsonwebtoken_mod::algorithm::{Algorithm, AlgorithmFamily, AsStr};
+use crate::jsonwebtoken_mod::crypto::b64_encode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+/// A JSON Web Key, RFC 7517, with the members of the key types in RFC 7518 and RFC 8037
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Jwk {
+  pub kty: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub kid: Option<String>,
+  #[serde(rename = "use", skip_serializing_if = "Option::is_none")]
+  pub use_: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub key_ops: Option<Vec<String>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub alg: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub crv: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub x: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub y: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub n: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub e: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub d: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub p: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub q: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub dp: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub dq: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub qi: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub k: Option<String>,
+}
+
+/// A JWK Set, RFC 7517 section 5
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JwkSet {
+  pub keys: Vec<Jwk>,
+}
+
+impl JwkSet {
+  /// The index of the one key for a token, by its `kid` if it has one and by what fits its `alg`
+  pub fn find(&self, kid: Option<&str>, alg: Algorithm) -> Result<usize> {
+    let mut candidates = self
+      .keys
+      .iter()
+      .enumerate()
+      .filter(|(_, jwk)| kid.is_none() || jwk.kid.as_deref() == kid)
+      .filter(|(_, jwk)| jwk.fits(alg))
+      .map(|(index, _)| index);
+
+    match (candidates.next(), candidates.next()) {
+      (Some(index), None) => Ok(index),
+      (None, _) => bail!(ErrorKind::NoMatchingKey),
+      (Some(_), Some(_)) => bail!(ErrorKind::MultipleMatchingKeys),
+    }
+  }
+}
+
+/// The hash of a thumbprint
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ThumbprintHash {
+  #[default]
+  Sha256,
+  Sha384,
+  Sha512,
+}
+
+impl ThumbprintHash {
+  /// By the name Node's `crypto` gives it
+  pub fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "sha256" => Some(ThumbprintHash::Sha256),
+      "sha384" => Some(ThumbprintHash::Sha384),
+      "sha512" => Some(ThumbprintHash::Sha512),
+      _ => None,
+    }
+  }
+}
+
+/// What a key is used for, as the `key_ops` member names it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyOperation {
+  Sign,
+  Verify,
+}
+
+impl AsStr for KeyOperation {
+  fn as_str(&self) -> &'static str {
+    match self {
+      KeyOperation::Sign => "sign",
+      KeyOperation::Verify => "verify",
+    }
+  }
+}
+
+impl Jwk {
+  /// Decodes a base64url member of the key, which must be present
+  pub fn decode_member(member: &Option<String>) -> Result<Vec<u8>> {
+    let member = member.as_ref().ok_or(ErrorKind::InvalidKey)?;
+    base64::decode_config(member, base64::URL_SAFE_NO_PAD).map_err(|_| ErrorKind::InvalidKey.into())
+  }
+
+  /// The RFC 7638 thumbprint, the hash of the members that define the key,
+  /// in lexicographic order and without whitespace
+  pub fn thumbprint(&self, hash: ThumbprintHash) -> Result<String> {
+    let member = |member: &Option<String>| -> Result<String> {
+      Ok(serde_json::to_string(
+        member.as_ref().ok_or(ErrorKind::InvalidKey)?,
+      )?)
+    };
+    let members = match self.kty.as_str() {
+      "RSA" => format!(
+        r#"{{"e":{},"kty":"RSA","n":{}}}"#,
+        member(&self.e)?,
+        member(&self.n)?
+      ),
+      "EC" => format!(
+        r#"{{"crv":{},"kty":"EC","x":{},"y":{}}}"#,
+        member(&self.crv)?,
+        member(&self.x)?,
+        member(&self.y)?
+      ),
+      "OKP" => format!(
+        r#"{{"crv":{},"kty":"OKP","x":{}}}"#,
+        member(&self.crv)?,
+        member(&self.x)?
+      ),
+      "oct" => format!(r#"{{"k":{},"kty":"oct"}}"#, member(&self.k)?),
+      kty => bail!(ErrorKind::UnsupportedKey(format!("{} JWK", kty))),
+    };
+
+    let members = members.as_bytes();
+    Ok(match hash {
+      ThumbprintHash::Sha256 => b64_encode(&Sha256::digest(members)),
+      ThumbprintHash::Sha384 => b64_encode(&Sha384::digest(members)),
+      ThumbprintHash::Sha512 => b64_encode(&Sha512::digest(members)),
+    })
+  }
+
+  /// Whether the key could verify a token signed with `alg`
+  pub fn fits(&self, alg: Algorithm) -> bool {
+    let crv = alg.curve();
+
+    self.check_usage(alg, KeyOperation::Verify).is_ok()
+      && (crv.is_none() || self.crv.as_deref() == crv)
+  }
+
+  /// Checks that the key may do `operation` with `alg`, by its `kty`, `use`, `key_ops` and `alg`
+  pub fn check_usage(&self, alg: Algorithm, operation: KeyOperation) -> Result<()> {
+    let (kty, expected) = match alg.family() {
+      AlgorithmFamily::Hmac => ("oct", "a symmetric"),
+      AlgorithmFamily::Rsa => ("RSA", "an RSA"),
+      AlgorithmFamily::Ec => ("EC", "an EC"),
+      AlgorithmFamily::Okp => ("OKP", "an OKP"),
+      AlgorithmFamily::Unsecured => bail!(ErrorKind::InvalidAlgorithm),
+    };
+    if self.kty != kty {
+      bail!(ErrorKind::InvalidKeyType(expected, alg.as_str()));
+    }
+
+    if let Some(use_) = self.use_.as_deref().filter(|&use_| use_ != "sig") {
+      bail!(ErrorKind::InvalidKeyUsage(
+        "use",
+        format!("is \"{}\", not \"sig\"", use_)
+      ));
+    }
+    if let Some(key_ops) = &self.key_ops {
+      if !key_ops.iter().any(|op| op == operation.as_str()) {
+        let reason = format!("does not include \"{}\"", operation.as_str());
+        bail!(ErrorKind::InvalidKeyUsage("key_ops", reason));
+      }
+    }
+    if let Some(key_alg) = self
+      .alg
+      .as_deref()
+      .filter(|&key_alg| key_alg != alg.as_str())
+    {
+      let reason = format!("is \"{}\", not \"{}\"", key_alg, alg.as_str());
+      bail!(ErrorKind::InvalidKeyUsage("alg", reason));
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn jwk(kty: &str) -> Jwk {
+    Jwk {
+      kty: kty.to_string(),
+      ..Jwk::default()
+    }
+  }
+
+  fn kind(result: Result<()>) -> ErrorKind {
+    result.map_err(|e| e.0).unwrap_err()
+  }
+
+  #[test]
+  fn test_check_usage() {
+    let mut key = jwk("RSA");
+    assert!(key
+      .check_usage(Algorithm::RS256, KeyOperation::Sign)
+      .is_ok());
+    assert!(key
+      .check_usage(Algorithm::PS512, KeyOperation::Verify)
+      .is_ok());
+    let result = key.check_usage(Algorithm::HS256, KeyOperation::Verify);
+    assert!(matches!(
+      kind(result),
+      ErrorKind::InvalidKeyType("a symmetric", "HS256")
+    ));
+    let result = key.check_usage(Algorithm::ES256, KeyOperation::Verify);
+    assert!(matches!(
+      kind(result),
+      ErrorKind::InvalidKeyType("an EC", "ES256")
+    ));
+
+    key.use_ = Some("enc".to_string());
+    let result = key.check_usage(Algorithm::RS256, KeyOperation::Sign);
+    assert!(matches!(kind(result), ErrorKind::InvalidKeyUsage("use", _)));
+    key.use_ = Some("sig".to_string());
+
+    key.key_ops = Some(vec!["verify".to_string()]);
+    assert!(key
+      .check_usage(Algorithm::RS256, KeyOperation::Verify)
+      .is_ok());
+    let result = key.check_usage(Algorithm::RS256, KeyOperation::Sign);
+    assert!(matches!(
+      kind(result),
+      ErrorKind::InvalidKeyUsage("key_ops", _)
+    ));
+
+    key.alg = Some("RS256".to_string());
+    assert!(key
+      .check_usage(Algorithm::RS256, KeyOperation::Verify)
+      .is_ok());
+    let result = key.check_usage(Algorithm::RS384, KeyOperation::Verify);
+    assert!(matches!(kind(result), ErrorKind::InvalidKeyUsage("alg", _)));
+
+    assert!(jwk("oct")
+      .check_usage(Algorithm::HS384, KeyOperation::Sign)
+      .is_ok());
+    assert!(jwk("OKP")
+      .check_usage(Algorithm::EdDSA, KeyOperation::Sign)
+      .is_ok());
+  }
+
+  #[test]
+  fn test_jwk_set_find() {
+    let key = |kid: Option<&str>, kty: &str, crv: Option<&str>| Jwk {
+      kid: kid.map(String::from),
+      crv: crv.map(String::from),
+      ..jwk(kty)
+    };
+    let set = JwkSet {
+      keys: vec![
+        key(Some("a"), "RSA", None),
+        key(Some("b"), "RSA", None),
+        key(Some("c"), "EC", Some("P-256")),
+        key(Some("c"), "EC", Some("P-384")),
+        key(None, "oct", None),
+      ],
+    };
+
+    assert_eq!(set.find(Some("b"), Algorithm::RS256).unwrap(), 1);
+    assert_eq!(set.find(Some("c"), Algorithm::ES384).unwrap(), 3);
+    assert_eq!(set.find(None, Algorithm::ES256).unwrap(), 2);
+    assert_eq!(set.find(None, Algorithm::HS256).unwrap(), 4);
+
+    let result = set.find(Some("a"), Algorithm::ES256).map(|_| ());
+    assert!(matches!(kind(result), ErrorKind::NoMatchingKey));
+    let result = set.find(Some("d"), Algorithm::RS256).map(|_| ());
+    assert!(matches!(kind(result), ErrorKind::NoMatchingKey));
+    let result = set.find(None, Algorithm::ES512).map(|_| ());
+    assert!(matches!(kind(result), ErrorKind::NoMatchingKey));
+    let result = set.find(None, Algorithm::RS256).map(|_| ());
+    assert!(matches!(kind(result), ErrorKind::MultipleMatchingKeys));
+  }
+
+  #[test]
+  fn test_thumbprint() {
+    // RFC 7638 section 3.1
+    let key = Jwk {
+      n: Some(
+        "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw"
+          .to_string(),
+      ),
+      e: Some("AQAB".to_string()),
+      alg: Some("RS256".to_string()),
+      kid: Some("2011-04-29".to_string()),
+      ..jwk("RSA")
+    };
+    assert_eq!(
+      key.thumbprint(ThumbprintHash::Sha256).unwrap(),
+      "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+    );
+    assert_eq!(key.thumbprint(ThumbprintHash::Sha512).unwrap().len(), 86);
+
+    let result = jwk("EC").thumbprint(ThumbprintHash::Sha256).map(|_| ());
+    assert!(matches!(kind(result), ErrorKind::InvalidKey));
+  }
+}
@@ -0,0 +1,190 @@
+use crate::errors::{ErrorKind, Result};
+use crate::jsonwebtoken_mod::key_material::is_pem;
+use p521::elliptic_curve::sec1::{
+  FromEncodedPoint, ModulusSize, ToEncodedPoint, ValidatePublicKey,
+};
+use p521::elliptic_curve::{AffinePoint, CurveArithmetic, FieldBytesSize, SecretKey};
+use pkcs8::der::pem::{self, LineEnding};
+use pkcs8::der::{Decode, Encode};
+use pkcs8::SubjectPublicKeyInfoRef;
+use pkcs8::{AssociatedOid, EncodePrivateKey, EncryptedPrivateKeyInfo, PrivateKeyInfo};
+use x509_cert::Certificate;
+
+const CERTIFICATE: &str = "CERTIFICATE";
+const PUBLIC_KEY: &str = "PUBLIC KEY";
+const PRIVATE_KEY: &str = "PRIVATE KEY";
+const ENCRYPTED_PRIVATE_KEY: &str = "ENCRYPTED PRIVATE KEY";
+const RSA_PRIVATE_KEY: &str = "RSA PRIVATE KEY";
+const RSA_PUBLIC_KEY: &str = "RSA PUBLIC KEY";
+const EC_PRIVATE_KEY: &str = "EC PRIVATE KEY";
+
+/// The bytes of a key in a format the algorithms read. The PEM of a PKCS#8, SPKI or PKCS#1 key
+/// is kept and its DER gets that PEM, a SEC1 EC key gets its PKCS#8 PEM, an X.509 certificate
+/// the SPKI PEM of its public key and an encrypted PKCS#8 key is decrypted with `passphrase`.
+/// Other bytes, like a secret, are kept
+pub fn normalize(bytes: Vec<u8>, passphrase: Option<&[u8]>) -> Result<Vec<u8>> {
+  if is_pem(&bytes) {
+    let (label, der) = match pem::decode_vec(&bytes) {
+      Ok(pem) => pem,
+      // left for the algorithm to reject
+      Err(_) => return Ok(bytes),
+    };
+    return match label {
+      CERTIFICATE | ENCRYPTED_PRIVATE_KEY | EC_PRIVATE_KEY => to_pem(label, &der, passphrase),
+      _ => Ok(bytes),
+    };
+  }
+
+  match der_label(&bytes) {
+    Some(label) => to_pem(label, &bytes, passphrase),
+    None => Ok(bytes),
+  }
+}
+
+/// The PEM label of a DER, none when the bytes are not the DER of a key
+fn der_label(der: &[u8]) -> Option<&'static str> {
+  // every format is a SEQUENCE
+  if der.first() != Some(&0x30) {
+    return None;
+  }
+
+  let label = if Certificate::from_der(der).is_ok() {
+    CERTIFICATE
+  } else if SubjectPublicKeyInfoRef::from_der(der).is_ok() {
+    PUBLIC_KEY
+  } else if PrivateKeyInfo::from_der(der).is_ok() {
+    PRIVATE_KEY
+  } else if EncryptedPrivateKeyInfo::from_der(der).is_ok() {
+    ENCRYPTED_PRIVATE_KEY
+  } else if ::rsa::pkcs1::RsaPrivateKey::from_der(der).is_ok() {
+    RSA_PRIVATE_KEY
+  } else if ::rsa::pkcs1::RsaPublicKey::from_der(der).is_ok() {
+    RSA_PUBLIC_KEY
+  } else if sec1_to_pkcs8(der).is_ok() {
+    EC_PRIVATE_KEY
+  } else {
+    return None;
+  };
+  Some(label)
+}
+
+/// The PEM of the DER of a key with the PEM label `label`
+fn to_pem(label: &str, der: &[u8], passphrase: Option<&[u8]>) -> Result<Vec<u8>> {
+  let pem = match label {
+    CERTIFICATE => {
+      let certificate = Certificate::from_der(der).map_err(|_| ErrorKind::InvalidKey)?;
+      let public_key = certificate
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .map_err(|_| ErrorKind::InvalidKey)?;
+      encode(PUBLIC_KEY, &public_key)?
+    }
+    ENCRYPTED_PRIVATE_KEY => {
+      let passphrase = passphrase.ok_or(ErrorKind::InvalidArgument(
+        "passphrase",
+        "is required for an encrypted private key",
+      ))?;
+      let encrypted = EncryptedPrivateKeyInfo::from_der(der).map_err(|_| ErrorKind::InvalidKey)?;
+      let private_key = encrypted.decrypt(passphrase).map_err(|_| {
+        ErrorKind::InvalidArgument("passphrase", "does not decrypt the private key")
+      })?;
+      encode(PRIVATE_KEY, private_key.as_bytes())?
+    }
+    // ring only reads PKCS#8
+    EC_PRIVATE_KEY => sec1_to_pkcs8(der)?,
+    label => encode(label, der)?,
+  };
+
+  Ok(pem.into_bytes())
+}
+
+fn encode(label: &str, der: &[u8]) -> Result<String> {
+  pem::encode_string(label, LineEnding::LF, der).map_err(|_| ErrorKind::InvalidKey.into())
+}
+
+/// The PKCS#8 PEM of a SEC1 EC private key, on whichever curve it names
+fn sec1_to_pkcs8(der: &[u8]) -> Result<String> {
+  curve_sec1_to_pkcs8::<p256::NistP256>(der)
+    .or_else(|| curve_sec1_to_pkcs8::<p384::NistP384>(der))
+    .or_else(|| curve_sec1_to_pkcs8::<p521::NistP521>(der))
+    .or_else(|| curve_sec1_to_pkcs8::<k256::Secp256k1>(der))
+    .ok_or_else(|| ErrorKind::InvalidKey.into())
+}
+
+fn curve_sec1_to_pkcs8<C>(der: &[u8]) -> Option<String>
+where
+  C: CurveArithmetic + AssociatedOid + ValidatePublicKey,
+  AffinePoint<C>: FromEncodedPoint<C> + ToEncodedPoint<C>,
+  FieldBytesSize<C>: ModulusSize,
+{
+  let secret_key = SecretKey::<C>::from_sec1_der(der).ok()?;
+  let pem = secret_key.to_pkcs8_pem(LineEnding::LF).ok()?;
+  Some(pem.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::jsonwebtoken_mod::algorithm::{Algorithm, Key};
+  use crate::jsonwebtoken_mod::crypto::{sign, verify};
+  use crate::jsonwebtoken_mod::key_material::KeyMaterial;
+  use pkcs8::der::Document;
+
+  fn der(pem: &str) -> Vec<u8> {
+    Document::from_pem(pem).unwrap().1.as_bytes().to_vec()
+  }
+
+  #[test]
+  fn test_der_and_sec1_keys() {
+    for alg in [Algorithm::ES256, Algorithm::ES384, Algorithm::RS256] {
+      let key_pair = alg.generate_key_pair(None).unwrap();
+      let private_der = der(&key_pair.private_key.pem);
+      let mut private_keys = vec![private_der.clone()];
+      if alg != Algorithm::RS256 {
+        // the ECPrivateKey inside the PKCS#8
+        let sec1 = PrivateKeyInfo::from_der(&private_der)
+          .unwrap()
+          .private_key
+          .to_vec();
+        private_keys.push(sec1.clone());
+        private_keys.push(encode(EC_PRIVATE_KEY, &sec1).unwrap().into_bytes());
+      }
+      let public_key = normalize(der(&key_pair.public_key.pem), None).unwrap();
+      assert_eq!(public_key, key_pair.public_key.pem.as_bytes());
+
+      for private_key in private_keys {
+        let private_key = KeyMaterial::Bytes(normalize(private_key, None).unwrap());
+        let signature = sign("message", &alg.get_encoding_key(&private_key).unwrap(), alg).unwrap();
+        let key = alg
+          .get_decoding_key(&KeyMaterial::Bytes(public_key.clone()))
+          .unwrap();
+        assert!(verify(&signature, "message", &key, alg).unwrap());
+      }
+    }
+  }
+
+  #[test]
+  fn test_encrypted_keys() {
+    let key_pair = Algorithm::ES256.generate_key_pair(None).unwrap();
+    let private_der = der(&key_pair.private_key.pem);
+    let encrypted = PrivateKeyInfo::from_der(&private_der)
+      .unwrap()
+      .encrypt(rand_core::OsRng, "passphrase")
+      .unwrap();
+    let encrypted = encode(ENCRYPTED_PRIVATE_KEY, encrypted.as_bytes()).unwrap();
+
+    let private_key = normalize(encrypted.clone().into_bytes(), Some(b"passphrase")).unwrap();
+    assert_eq!(private_key, key_pair.private_key.pem.as_bytes());
+    assert!(normalize(encrypted.clone().into_bytes(), None).is_err());
+    assert!(normalize(encrypted.into_bytes(), Some(b"wrong")).is_err());
+  }
+
+  #[test]
+  fn test_secrets_are_kept() {
+    let secret = b"0123456789abcdef0123456789abcdef".to_vec();
+    assert_eq!(normalize(secret.clone(), None).unwrap(), secret);
+    let secret = vec![0x30, 0x03, 0x02, 0x01, 0x00];
+    assert_eq!(normalize(secret.clone(), None).unwrap(), secret);
+  }
+}
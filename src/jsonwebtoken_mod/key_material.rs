@@ -0,0 +1,103 @@
+use crate::errors::{ErrorKind, Result};
+use crate::jsonwebtoken_mod::crypto::b64_encode;
+use crate::jsonwebtoken_mod::jwk::{Jwk, JwkSet, ThumbprintHash};
+use crate::jsonwebtoken_mod::key_format::normalize;
+use crate::jsonwebtoken_mod::public_key::PublicKey;
+use crate::jsonwebtoken_mod::remote_jwks::RemoteJwks;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// A key as `sign` and `verify` take it, before an algorithm parses it
+#[derive(Debug, Clone)]
+pub enum KeyMaterial {
+  /// a secret, or a PEM
+  Bytes(Vec<u8>),
+  Jwk(Box<Jwk>),
+}
+
+impl KeyMaterial {
+  /// A key given as bytes, in any format `key_format::normalize` reads
+  pub fn from_bytes(bytes: Vec<u8>, passphrase: Option<&[u8]>) -> Result<Self> {
+    Ok(KeyMaterial::Bytes(normalize(bytes, passphrase)?))
+  }
+
+  /// The HMAC secret, the bytes themselves or the `k` of an oct JWK
+  pub fn secret(&self) -> Result<Cow<'_, [u8]>> {
+    match self {
+      KeyMaterial::Bytes(bytes) => Ok(Cow::Borrowed(bytes)),
+      KeyMaterial::Jwk(jwk) => Ok(Cow::Owned(Jwk::decode_member(&jwk.k)?)),
+    }
+  }
+
+  /// The RFC 7638 thumbprint of the key, of its public half when it is a private key,
+  /// or of an oct JWK when it is a secret
+  pub fn thumbprint(&self, hash: ThumbprintHash) -> Result<String> {
+    match self {
+      KeyMaterial::Jwk(jwk) => jwk.thumbprint(hash),
+      KeyMaterial::Bytes(bytes) if is_pem(bytes) => PublicKey::new(self)?.to_jwk().thumbprint(hash),
+      KeyMaterial::Bytes(bytes) => Jwk {
+        kty: "oct".to_string(),
+        k: Some(b64_encode(bytes)),
+        ..Jwk::default()
+      }
+      .thumbprint(hash),
+    }
+  }
+}
+
+/// A key as `verify` takes it, one key or a JWK Set to pick it from
+#[derive(Debug, Clone)]
+pub enum KeySource {
+  Key(KeyMaterial),
+  Set(JwkSet),
+  Remote(Arc<RemoteJwks>),
+}
+
+/// A key as read from a javascript argument, before `parse` reads its format,
+/// which for DER, certificates and encrypted keys is worth keeping off the main thread
+#[derive(Debug, Clone)]
+pub enum RawKey {
+  /// a secret or a key in any format `KeyMaterial::from_bytes` reads, and its passphrase
+  Bytes(Vec<u8>, Option<Vec<u8>>),
+  Jwk(Box<Jwk>),
+}
+
+impl RawKey {
+  pub fn parse(self) -> Result<KeyMaterial> {
+    match self {
+      RawKey::Bytes(bytes, passphrase) => KeyMaterial::from_bytes(bytes, passphrase.as_deref()),
+      RawKey::Jwk(jwk) => Ok(KeyMaterial::Jwk(jwk)),
+    }
+  }
+}
+
+/// A `KeySource` as read from a javascript argument, before `parse` reads the format of its key
+#[derive(Debug, Clone)]
+pub enum RawKeySource {
+  Key(RawKey),
+  Set(JwkSet),
+  Remote(Arc<RemoteJwks>),
+}
+
+impl RawKeySource {
+  pub fn parse(self) -> Result<KeySource> {
+    Ok(match self {
+      RawKeySource::Key(key) => KeySource::Key(key.parse()?),
+      RawKeySource::Set(set) => KeySource::Set(set),
+      RawKeySource::Remote(remote) => KeySource::Remote(remote),
+    })
+  }
+}
+
+pub fn is_pem(key: &[u8]) -> bool {
+  let start = key
+    .iter()
+    .position(|b| !b.is_ascii_whitespace())
+    .unwrap_or(key.len());
+  key[start..].starts_with(b"-----BEGIN ")
+}
+
+/// The text of a PEM
+pub fn pem(key: &[u8]) -> Result<&str> {
+  std::str::from_utf8(key).map_err(|_| ErrorKind::InvalidKey.into())
+}
@@ -0,0 +1,134 @@
+use crate::errors::Result;
+use crate::jsonwebtoken_mod::algorithm::{Algorithm, AsStr};
+use crate::jsonwebtoken_mod::jwk::Jwk;
+use crate::jsonwebtoken_mod::public_key::PublicKey;
+use serde::Serialize;
+
+/// A generated key pair, each key as a PEM and as a JWK for its algorithm
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyPair {
+  pub private_key: KeyForms,
+  pub public_key: KeyForms,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyForms {
+  pub pem: String,
+  pub jwk: Jwk,
+}
+
+impl KeyPair {
+  /// From the PKCS#8 PEM of the private key, its public key and the private members of its JWK
+  pub fn new(
+    alg: Algorithm,
+    private_pem: String,
+    public_key: PublicKey,
+    private_members: Jwk,
+  ) -> Result<Self> {
+    let public_jwk = Jwk {
+      alg: Some(alg.as_str().to_string()),
+      ..public_key.to_jwk()
+    };
+    let private_jwk = Jwk {
+      d: private_members.d,
+      p: private_members.p,
+      q: private_members.q,
+      dp: private_members.dp,
+      dq: private_members.dq,
+      qi: private_members.qi,
+      ..public_jwk.clone()
+    };
+
+    Ok(KeyPair {
+      private_key: KeyForms {
+        pem: private_pem,
+        jwk: private_jwk,
+      },
+      public_key: KeyForms {
+        pem: public_key.to_pem()?,
+        jwk: public_jwk,
+      },
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::jsonwebtoken_mod::algorithm::{Algorithm, Key};
+  use crate::jsonwebtoken_mod::crypto::{sign, verify};
+  use crate::jsonwebtoken_mod::key_material::KeyMaterial;
+
+  #[test]
+  fn test_generated_keys_sign_and_verify() {
+    for alg in [
+      Algorithm::ES256,
+      Algorithm::ES384,
+      Algorithm::ES512,
+      Algorithm::ES256K,
+      Algorithm::EdDSA,
+    ] {
+      let key_pair = alg.generate_key_pair(None).unwrap();
+      let keys = |pem: &str, jwk| {
+        [
+          KeyMaterial::Bytes(pem.as_bytes().to_vec()),
+          KeyMaterial::Jwk(Box::new(jwk)),
+        ]
+      };
+      let private_keys = keys(&key_pair.private_key.pem, key_pair.private_key.jwk);
+      let public_keys = keys(&key_pair.public_key.pem, key_pair.public_key.jwk);
+
+      for private_key in &private_keys {
+        let signature = sign("message", &alg.get_encoding_key(private_key).unwrap(), alg).unwrap();
+        for public_key in &public_keys {
+          let key = alg.get_decoding_key(public_key).unwrap();
+          assert!(verify(&signature, "message", &key, alg).unwrap());
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_largest_rsa_key_pair_signs() {
+    let alg = Algorithm::PS256;
+    let key_pair = alg.generate_key_pair(Some(4096)).unwrap();
+    let private_pem = KeyMaterial::Bytes(key_pair.private_key.pem.into_bytes());
+    // a private key PEM verifies with its public half
+    let public_keys = [
+      KeyMaterial::Bytes(key_pair.public_key.pem.into_bytes()),
+      private_pem.clone(),
+    ]
+    .map(|key| alg.get_decoding_key(&key).unwrap());
+
+    for private_key in [
+      private_pem,
+      KeyMaterial::Jwk(Box::new(key_pair.private_key.jwk)),
+    ] {
+      let signature = sign("message", &alg.get_encoding_key(&private_key).unwrap(), alg).unwrap();
+      for public_key in &public_keys {
+        assert!(verify(&signature, "message", public_key, alg).unwrap());
+      }
+    }
+  }
+
+  #[test]
+  fn test_generated_secrets() {
+    assert_eq!(Algorithm::HS384.generate_secret().unwrap().len(), 48);
+    assert!(Algorithm::RS256.generate_secret().is_err());
+    assert!(Algorithm::HS256.generate_key_pair(None).is_err());
+    assert!(Algorithm::RS256.generate_key_pair(Some(1024)).is_err());
+    // ring signs with no larger key
+    assert!(Algorithm::RS256.generate_key_pair(Some(4104)).is_err());
+    assert!(Algorithm::ES256.generate_key_pair(Some(2048)).is_err());
+
+    for error in [
+      Algorithm::None.generate_key_pair(None).unwrap_err(),
+      Algorithm::None.generate_secret().unwrap_err(),
+    ] {
+      assert_eq!(
+        error.to_string(),
+        "alg is \"none\", for unsecured tokens, which take no key"
+      );
+    }
+  }
+}
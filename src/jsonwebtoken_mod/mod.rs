@@ -1,2 +1,14 @@
 pub mod algorithm;
+pub mod crypto;
+pub mod decoding;
+pub mod ec;
+pub mod eddsa;
+pub mod encoding;
 pub mod header;
+pub mod jwk;
+pub mod key_format;
+pub mod key_material;
+pub mod key_pair;
+pub mod public_key;
+pub mod remote_jwks;
+pub mod rsa;
@@ -0,0 +1,176 @@
+use crate::errors::{ErrorKind, Result};
+use crate::jsonwebtoken_mod::crypto::b64_encode;
+use crate::jsonwebtoken_mod::jwk::Jwk;
+use crate::jsonwebtoken_mod::key_material::{is_pem, KeyMaterial};
+use crate::jsonwebtoken_mod::{ec, eddsa, rsa};
+use ::rsa::traits::PublicKeyParts;
+use ::rsa::RsaPublicKey;
+use ed25519_dalek::VerifyingKey;
+use p521::elliptic_curve::pkcs8::{EncodePublicKey, LineEnding};
+use p521::elliptic_curve::sec1::ToEncodedPoint;
+
+/// The public half of an asymmetric key, for publishing it
+#[derive(Debug, Clone)]
+pub enum PublicKey {
+  Rsa(RsaPublicKey),
+  P256(p256::PublicKey),
+  P384(p384::PublicKey),
+  P521(p521::PublicKey),
+  K256(k256::PublicKey),
+  Ed25519(VerifyingKey),
+}
+
+impl PublicKey {
+  /// From the PEM of a private or public key, or a JWK, secrets have no public half
+  pub fn new(key: &KeyMaterial) -> Result<Self> {
+    match key {
+      KeyMaterial::Bytes(bytes) if !is_pem(bytes) => bail!(ErrorKind::InvalidArgument(
+        "key",
+        "must be a PEM or a JWK of an asymmetric key, secrets have no public key"
+      )),
+      KeyMaterial::Bytes(_) => rsa::public_key(key)
+        .map(PublicKey::Rsa)
+        .or_else(|_| ec::public_key(key, "P-256").map(PublicKey::P256))
+        .or_else(|_| ec::public_key(key, "P-384").map(PublicKey::P384))
+        .or_else(|_| ec::public_key(key, "P-521").map(PublicKey::P521))
+        .or_else(|_| ec::public_key(key, "secp256k1").map(PublicKey::K256))
+        .or_else(|_| eddsa::verifying_key(key).map(PublicKey::Ed25519)),
+      KeyMaterial::Jwk(jwk) => match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+        ("RSA", _) => Ok(PublicKey::Rsa(rsa::public_key(key)?)),
+        ("EC", Some("P-256")) => Ok(PublicKey::P256(ec::public_key(key, "P-256")?)),
+        ("EC", Some("P-384")) => Ok(PublicKey::P384(ec::public_key(key, "P-384")?)),
+        ("EC", Some("P-521")) => Ok(PublicKey::P521(ec::public_key(key, "P-521")?)),
+        ("EC", Some("secp256k1")) => Ok(PublicKey::K256(ec::public_key(key, "secp256k1")?)),
+        ("OKP", _) => Ok(PublicKey::Ed25519(eddsa::verifying_key(key)?)),
+        ("oct", _) => bail!(ErrorKind::InvalidArgument(
+          "key",
+          "must be a PEM or a JWK of an asymmetric key, secrets have no public key"
+        )),
+        _ => bail!(ErrorKind::UnsupportedKey(format!("{} JWK", jwk.kty))),
+      },
+    }
+  }
+
+  /// The JWK `crv` of an EC or OKP key
+  pub fn curve(&self) -> Option<&'static str> {
+    match self {
+      PublicKey::Rsa(_) => None,
+      PublicKey::P256(_) => Some("P-256"),
+      PublicKey::P384(_) => Some("P-384"),
+      PublicKey::P521(_) => Some("P-521"),
+      PublicKey::K256(_) => Some("secp256k1"),
+      PublicKey::Ed25519(_) => Some("Ed25519"),
+    }
+  }
+
+  /// The public JWK, without `kid`, `use`, `key_ops` or `alg`
+  pub fn to_jwk(&self) -> Jwk {
+    let ec = |crv: &str, point: &[u8]| {
+      // the uncompressed SEC1 encoding, `x` and `y` are the halves after the tag
+      let (x, y) = point[1..].split_at((point.len() - 1) / 2);
+      Jwk {
+        kty: "EC".to_string(),
+        crv: Some(crv.to_string()),
+        x: Some(b64_encode(x)),
+        y: Some(b64_encode(y)),
+        ..Jwk::default()
+      }
+    };
+
+    match self {
+      PublicKey::Rsa(key) => Jwk {
+        kty: "RSA".to_string(),
+        n: Some(b64_encode(&key.n().to_bytes_be())),
+        e: Some(b64_encode(&key.e().to_bytes_be())),
+        ..Jwk::default()
+      },
+      PublicKey::P256(key) => ec("P-256", key.to_encoded_point(false).as_bytes()),
+      PublicKey::P384(key) => ec("P-384", key.to_encoded_point(false).as_bytes()),
+      PublicKey::P521(key) => ec("P-521", key.to_encoded_point(false).as_bytes()),
+      PublicKey::K256(key) => ec("secp256k1", key.to_encoded_point(false).as_bytes()),
+      PublicKey::Ed25519(key) => Jwk {
+        kty: "OKP".to_string(),
+        crv: Some("Ed25519".to_string()),
+        x: Some(b64_encode(key.as_bytes())),
+        ..Jwk::default()
+      },
+    }
+  }
+
+  /// The SPKI PEM
+  pub fn to_pem(&self) -> Result<String> {
+    let pem = match self {
+      PublicKey::Rsa(key) => key.to_public_key_pem(LineEnding::LF),
+      PublicKey::P256(key) => key.to_public_key_pem(LineEnding::LF),
+      PublicKey::P384(key) => key.to_public_key_pem(LineEnding::LF),
+      PublicKey::P521(key) => key.to_public_key_pem(LineEnding::LF),
+      PublicKey::K256(key) => key.to_public_key_pem(LineEnding::LF),
+      PublicKey::Ed25519(key) => key.to_public_key_pem(LineEnding::LF),
+    };
+
+    pem.map_err(|_| ErrorKind::InvalidKey.into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use ed25519_dalek::SigningKey;
+  use p521::elliptic_curve::pkcs8::EncodePrivateKey;
+
+  fn pem(pem: String) -> KeyMaterial {
+    KeyMaterial::Bytes(pem.into_bytes())
+  }
+
+  #[test]
+  fn test_public_key_from_private_pems() {
+    let secret_key = p256::SecretKey::from_slice(&[3; 32]).unwrap();
+    let private_pem = secret_key.to_pkcs8_pem(LineEnding::LF).unwrap().to_string();
+    let public_key = PublicKey::new(&pem(private_pem)).unwrap();
+    assert!(matches!(public_key, PublicKey::P256(key) if key == secret_key.public_key()));
+
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    let private_pem = signing_key
+      .to_pkcs8_pem(LineEnding::LF)
+      .unwrap()
+      .to_string();
+    let public_key = PublicKey::new(&pem(private_pem)).unwrap();
+    assert_eq!(
+      public_key.to_pem().unwrap(),
+      signing_key
+        .verifying_key()
+        .to_public_key_pem(LineEnding::LF)
+        .unwrap()
+    );
+  }
+
+  #[test]
+  fn test_public_key_round_trips_through_jwk() {
+    let public_key = p521::SecretKey::from_slice(&[1; 66]).unwrap().public_key();
+    let jwk = PublicKey::P521(public_key).to_jwk();
+    assert_eq!(jwk.crv.as_deref(), Some("P-521"));
+    assert_eq!(Jwk::decode_member(&jwk.x).unwrap().len(), 66);
+
+    let from_jwk = PublicKey::new(&KeyMaterial::Jwk(Box::new(jwk))).unwrap();
+    assert!(matches!(from_jwk, PublicKey::P521(key) if key == public_key));
+  }
+
+  #[test]
+  fn test_public_key_rejects_secrets() {
+    let result = PublicKey::new(&KeyMaterial::Bytes(b"secret".to_vec()));
+    assert!(matches!(
+      result.unwrap_err().0,
+      ErrorKind::InvalidArgument("key", _)
+    ));
+    let oct = Jwk {
+      kty: "oct".to_string(),
+      k: Some("c2VjcmV0".to_string()),
+      ..Jwk::default()
+    };
+    let result = PublicKey::new(&KeyMaterial::Jwk(Box::new(oct)));
+    assert!(matches!(
+      result.unwrap_err().0,
+      ErrorKind::InvalidArgument("key", _)
+    ));
+  }
+}
@@ -0,0 +1,482 @@
+use crate::errors::{Error, ErrorKind, Result};
+use crate::jsonwebtoken_mod::algorithm::Algorithm;
+use crate::jsonwebtoken_mod::decoding::decode_header;
+use crate::jsonwebtoken_mod::jwk::JwkSet;
+use crate::timespan::Interval;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
+
+/// The longest `cacheMaxAge`, `cooldown` and `timeout`, a year of 365.25 days as `"1y"` is
+const MAX_INTERVAL: Duration = Duration::from_secs(36525 * 24 * 60 * 60 / 100);
+/// How many sets are fetched at once, the others queue
+const FETCH_THREADS: usize = 4;
+
+/// The options of `createRemoteJwks`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RemoteJwksOptions {
+  /// how long a fetched set is fresh
+  pub cache_max_age: Interval,
+  /// how long to wait after a fetch before fetching again for an unknown `kid`, or after a failure
+  pub cooldown: Interval,
+  /// how long a fetch may take
+  pub timeout: Interval,
+}
+
+impl Default for RemoteJwksOptions {
+  fn default() -> Self {
+    RemoteJwksOptions {
+      cache_max_age: Interval(Duration::from_secs(10 * 60)),
+      cooldown: Interval(Duration::from_secs(30)),
+      timeout: Interval(Duration::from_secs(5)),
+    }
+  }
+}
+
+/// A JWK Set fetched over HTTP(S) and cached, for `verifyAsync` to pick keys from
+#[derive(Debug)]
+pub struct RemoteJwks {
+  url: String,
+  options: RemoteJwksOptions,
+  agent: ureq::Agent,
+  cache: Mutex<Cache>,
+  waiting: Mutex<Waiting>,
+}
+
+#[derive(Debug, Default)]
+struct Cache {
+  set: Option<Arc<JwkSet>>,
+  fetched_at: Option<Instant>,
+  attempted_at: Option<Instant>,
+  /// why the last fetch failed, when no set was ever fetched
+  failure: Option<Failure>,
+}
+
+#[derive(Debug, Clone)]
+enum Failure {
+  Fetch(String),
+  Invalid,
+}
+
+impl Failure {
+  fn to_error(&self) -> Error {
+    match self {
+      Failure::Fetch(reason) => ErrorKind::JwksFetch(reason.clone()).into(),
+      Failure::Invalid => ErrorKind::JwksInvalid.into(),
+    }
+  }
+}
+
+/// What runs once the fetch in flight lands, no fetch is in flight while it is empty
+#[derive(Default)]
+struct Waiting(Vec<Box<dyn FnOnce() + Send>>);
+
+impl fmt::Debug for Waiting {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} waiting", self.0.len())
+  }
+}
+
+impl RemoteJwks {
+  pub fn new(url: &str, options: RemoteJwksOptions) -> Result<Self> {
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+      bail!(ErrorKind::InvalidArgument(
+        "url",
+        "must be an http: or https: URL"
+      ));
+    }
+    for (option, interval) in [
+      ("cacheMaxAge", options.cache_max_age),
+      ("cooldown", options.cooldown),
+      ("timeout", options.timeout),
+    ] {
+      if interval.0 > MAX_INTERVAL {
+        bail!(ErrorKind::InvalidOption(
+          option,
+          "must be at most a year".to_string()
+        ));
+      }
+    }
+
+    Ok(RemoteJwks {
+      url: url.to_string(),
+      options,
+      agent: ureq::AgentBuilder::new().timeout(options.timeout.0).build(),
+      cache: Mutex::default(),
+      waiting: Mutex::default(),
+    })
+  }
+
+  /// The set to pick the key of a token from: the cached one, or the stale one when a fetch
+  /// failed or found no key. Never waits on the network, see `needs_fetch` and `fetch_then`
+  pub fn key_set(&self, kid: Option<&str>, alg: Algorithm) -> Result<Arc<JwkSet>> {
+    if let Some(set) = self.cached(kid, alg)? {
+      return Ok(set);
+    }
+
+    let cache = lock(&self.cache);
+    match (&cache.set, &cache.failure) {
+      (Some(set), _) => Ok(set.clone()),
+      (None, Some(failure)) => Err(failure.to_error()),
+      (None, None) => bail!(ErrorKind::JwksFetch(
+        "the set was not fetched yet".to_string()
+      )),
+    }
+  }
+
+  /// Whether the set should be fetched before picking the key of `token`,
+  /// when the cached one is stale or does not have the key, unless the last fetch
+  /// was less than a cooldown ago. A token whose header does not decode needs no key
+  pub fn needs_fetch(&self, token: &str) -> bool {
+    match decode_header(token) {
+      Ok(header) => matches!(self.cached(header.kid.as_deref(), header.alg), Ok(None)),
+      Err(_) => false,
+    }
+  }
+
+  /// Fetches the set on the fetch threads, then runs `then` there.
+  /// What misses the cache while a fetch is in flight waits for that one, holding no thread
+  pub fn fetch_then<F: FnOnce() + Send + 'static>(self: &Arc<Self>, then: F) {
+    let mut waiting = lock(&self.waiting);
+    waiting.0.push(Box::new(then));
+    if waiting.0.len() > 1 {
+      return;
+    }
+    drop(waiting);
+
+    let remote = Arc::clone(self);
+    let refresh = move || remote.refresh();
+    match fetch_pool() {
+      Some(pool) => pool.spawn(refresh),
+      // no thread to spare, which leaves the rayon pool
+      None => rayon::spawn(refresh),
+    }
+  }
+
+  /// Fetches the set, unless the last fetch was less than a cooldown ago,
+  /// then runs what waited for it. A failed fetch keeps the stale set, if there is one
+  fn refresh(&self) {
+    let attempted_at = lock(&self.cache).attempted_at;
+    if !within(attempted_at, self.options.cooldown) {
+      let result = self.fetch();
+      let mut cache = lock(&self.cache);
+      cache.attempted_at = Some(Instant::now());
+      match result {
+        Ok(set) => {
+          cache.set = Some(Arc::new(set));
+          cache.fetched_at = cache.attempted_at;
+          cache.failure = None;
+        }
+        Err(failure) if cache.set.is_none() => cache.failure = Some(failure),
+        Err(_) => {}
+      }
+    }
+
+    let waiting = std::mem::take(&mut lock(&self.waiting).0);
+    for then in waiting {
+      then();
+    }
+  }
+
+  /// The cached set, when it is fresh and has the key or when a fetch is cooling down
+  fn cached(&self, kid: Option<&str>, alg: Algorithm) -> Result<Option<Arc<JwkSet>>> {
+    let cache = lock(&self.cache);
+    let cooling_down = within(cache.attempted_at, self.options.cooldown);
+
+    match &cache.set {
+      Some(set) => {
+        let fresh = within(cache.fetched_at, self.options.cache_max_age);
+        // a `kid` the set does not have may be a key rotated in since
+        let has_key = !matches!(
+          set.find(kid, alg).map_err(|error| error.0),
+          Err(ErrorKind::NoMatchingKey)
+        );
+        Ok(if (fresh && has_key) || cooling_down {
+          Some(set.clone())
+        } else {
+          None
+        })
+      }
+      None => match &cache.failure {
+        Some(failure) if cooling_down => Err(failure.to_error()),
+        _ => Ok(None),
+      },
+    }
+  }
+
+  fn fetch(&self) -> std::result::Result<JwkSet, Failure> {
+    let response = self
+      .agent
+      .get(&self.url)
+      .set("Accept", "application/json")
+      .call()
+      .map_err(|error| Failure::Fetch(error.to_string()))?;
+    let body = response
+      .into_string()
+      .map_err(|error| Failure::Fetch(error.to_string()))?;
+
+    serde_json::from_str(&body).map_err(|_| Failure::Invalid)
+  }
+}
+
+fn within(at: Option<Instant>, interval: Interval) -> bool {
+  at.is_some_and(|at| at.elapsed() < interval.0)
+}
+
+/// The threads sets are fetched on, so that a slow endpoint holds up neither
+/// the crypto on the rayon pool nor more threads than these
+fn fetch_pool() -> Option<&'static rayon::ThreadPool> {
+  static POOL: OnceLock<Option<rayon::ThreadPool>> = OnceLock::new();
+  let pool = POOL.get_or_init(|| {
+    rayon::ThreadPoolBuilder::new()
+      .num_threads(FETCH_THREADS)
+      .thread_name(|index| format!("jwks-fetch-{}", index))
+      .build()
+      .ok()
+  });
+  pool.as_ref()
+}
+
+/// A panic while a lock was held leaves the cache consistent, so keep using it
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+  mutex.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+  use std::io::{Read, Write};
+  use std::net::TcpListener;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+  use std::sync::mpsc;
+  use std::thread;
+
+  /// A local stand-in for a JWKS endpoint, answering with `responses` in turn and then the last one
+  fn serve(responses: Vec<(u16, String)>) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/jwks.json", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+          let read = stream.read(&mut buffer).unwrap();
+          if read == 0 {
+            break;
+          }
+          request.extend_from_slice(&buffer[..read]);
+        }
+
+        let index = counter.fetch_add(1, Ordering::SeqCst);
+        let (status, body) = &responses[index.min(responses.len() - 1)];
+        let response = format!(
+          "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+          status,
+          body.len(),
+          body
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+      }
+    });
+
+    (url, requests)
+  }
+
+  fn jwks(kids: &[&str]) -> (u16, String) {
+    let keys: Vec<_> = kids
+      .iter()
+      .map(|kid| json!({"kty": "oct", "kid": kid, "k": "c2VjcmV0"}))
+      .collect();
+    (200, json!({ "keys": keys }).to_string())
+  }
+
+  fn options(cache_max_age: u64, cooldown: u64) -> RemoteJwksOptions {
+    RemoteJwksOptions {
+      cache_max_age: Interval(Duration::from_secs(cache_max_age)),
+      cooldown: Interval(Duration::from_secs(cooldown)),
+      ..RemoteJwksOptions::default()
+    }
+  }
+
+  fn new_remote(url: &str, options: RemoteJwksOptions) -> Arc<RemoteJwks> {
+    Arc::new(RemoteJwks::new(url, options).unwrap())
+  }
+
+  /// Picks the set like `verifyAsync`, fetching it first when the cache misses
+  fn key_set(remote: &Arc<RemoteJwks>, kid: Option<&str>) -> Result<Arc<JwkSet>> {
+    if matches!(remote.cached(kid, Algorithm::HS256), Ok(None)) {
+      let (sender, receiver) = mpsc::channel();
+      remote.fetch_then(move || sender.send(()).unwrap());
+      receiver.recv().unwrap();
+    }
+    remote.key_set(kid, Algorithm::HS256)
+  }
+
+  fn kids(set: Arc<JwkSet>) -> Vec<String> {
+    set.keys.iter().filter_map(|jwk| jwk.kid.clone()).collect()
+  }
+
+  #[test]
+  fn test_caches_the_set() {
+    let (url, requests) = serve(vec![jwks(&["a"])]);
+    let remote = new_remote(&url, options(600, 0));
+
+    assert_eq!(kids(key_set(&remote, Some("a")).unwrap()), ["a"]);
+    assert_eq!(kids(key_set(&remote, None).unwrap()), ["a"]);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+  }
+
+  #[test]
+  fn test_refetches_an_unknown_kid_once_per_cooldown() {
+    let (url, requests) = serve(vec![jwks(&["a"]), jwks(&["a", "b"])]);
+    let remote = new_remote(&url, options(600, 0));
+    key_set(&remote, Some("a")).unwrap();
+    assert_eq!(kids(key_set(&remote, Some("b")).unwrap()), ["a", "b"]);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    let (url, requests) = serve(vec![jwks(&["a"]), jwks(&["a", "b"])]);
+    let remote = new_remote(&url, options(600, 600));
+    key_set(&remote, Some("a")).unwrap();
+    assert_eq!(kids(key_set(&remote, Some("b")).unwrap()), ["a"]);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+  }
+
+  #[test]
+  fn test_serves_stale_keys_when_a_fetch_fails() {
+    let (url, requests) = serve(vec![jwks(&["a"]), (500, String::new())]);
+    let remote = new_remote(&url, options(0, 0));
+
+    key_set(&remote, Some("a")).unwrap();
+    assert_eq!(kids(key_set(&remote, Some("a")).unwrap()), ["a"]);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+  }
+
+  #[test]
+  fn test_fetch_failures() {
+    let (url, requests) = serve(vec![(404, String::new())]);
+    let remote = new_remote(&url, options(600, 600));
+    let result = key_set(&remote, None);
+    assert!(matches!(result.unwrap_err().0, ErrorKind::JwksFetch(_)));
+    // the failure is remembered for the cooldown
+    let result = key_set(&remote, None);
+    assert!(matches!(result.unwrap_err().0, ErrorKind::JwksFetch(_)));
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    let (url, _) = serve(vec![(200, "{\"keys\": 1}".to_string())]);
+    let remote = new_remote(&url, options(600, 0));
+    let result = key_set(&remote, None);
+    assert!(matches!(result.unwrap_err().0, ErrorKind::JwksInvalid));
+
+    let result = RemoteJwks::new("file:///etc/passwd", RemoteJwksOptions::default());
+    assert!(matches!(
+      result.unwrap_err().0,
+      ErrorKind::InvalidArgument("url", _)
+    ));
+  }
+
+  #[test]
+  fn test_needs_fetch() {
+    let (url, _) = serve(vec![jwks(&["a"])]);
+    let remote = new_remote(&url, options(600, 0));
+    let token = |kid: &str| {
+      let header = json!({"alg": "HS256", "kid": kid}).to_string();
+      format!(
+        "{}.e30.",
+        crate::jsonwebtoken_mod::crypto::b64_encode(header.as_bytes())
+      )
+    };
+
+    assert!(remote.needs_fetch(&token("a")));
+    key_set(&remote, Some("a")).unwrap();
+    assert!(!remote.needs_fetch(&token("a")));
+    assert!(remote.needs_fetch(&token("b")));
+    assert!(!remote.needs_fetch("not a token"));
+  }
+
+  #[test]
+  fn test_coalesces_fetches() {
+    let (url, requests) = serve(vec![jwks(&["a"])]);
+    let remote = new_remote(&url, options(600, 0));
+    let (sender, receiver) = mpsc::channel();
+
+    // every miss while the first fetch is held up waits for that fetch
+    let cache = remote.cache.lock().unwrap();
+    for _ in 0..20 {
+      let sender = sender.clone();
+      remote.fetch_then(move || sender.send(()).unwrap());
+    }
+    assert_eq!(lock(&remote.waiting).0.len(), 20);
+    drop(cache);
+
+    assert_eq!(receiver.iter().take(20).count(), 20);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    assert_eq!(
+      kids(remote.key_set(Some("a"), Algorithm::HS256).unwrap()),
+      ["a"]
+    );
+  }
+
+  #[test]
+  fn test_key_set_never_fetches() {
+    let (url, requests) = serve(vec![jwks(&["a"])]);
+    let remote = new_remote(&url, options(600, 0));
+
+    let result = remote.key_set(Some("a"), Algorithm::HS256);
+    assert!(matches!(result.unwrap_err().0, ErrorKind::JwksFetch(_)));
+    assert_eq!(requests.load(Ordering::SeqCst), 0);
+  }
+
+  #[test]
+  fn test_survives_a_poisoned_lock() {
+    let (url, _) = serve(vec![jwks(&["a"])]);
+    let remote = new_remote(&url, options(600, 0));
+    let poisoner = remote.clone();
+    let _ = thread::spawn(move || {
+      let _cache = poisoner.cache.lock().unwrap();
+      panic!("poison the cache");
+    })
+    .join();
+
+    assert!(remote.cache.is_poisoned());
+    assert_eq!(kids(key_set(&remote, Some("a")).unwrap()), ["a"]);
+  }
+
+  #[test]
+  fn test_intervals_out_of_range() {
+    for option in ["cacheMaxAge", "cooldown", "timeout"] {
+      for value in [json!(1e20), json!("999999999999999999999y"), json!("2y")] {
+        let options: RemoteJwksOptions = serde_json::from_value(json!({ option: value })).unwrap();
+        let result = RemoteJwks::new("https://example.com/jwks.json", options);
+        assert!(
+          matches!(result.unwrap_err().0, ErrorKind::InvalidOption(name, _) if name == option),
+          "{} {}",
+          option,
+          value
+        );
+      }
+    }
+    let options: RemoteJwksOptions = serde_json::from_value(json!({"cacheMaxAge": "1y"})).unwrap();
+    assert!(RemoteJwks::new("https://example.com/jwks.json", options).is_ok());
+  }
+
+  #[test]
+  fn test_times_out() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let options = RemoteJwksOptions {
+      timeout: Interval(Duration::from_millis(100)),
+      ..RemoteJwksOptions::default()
+    };
+    let remote = new_remote(&url, options);
+
+    let result = key_set(&remote, None);
+    assert!(matches!(result.unwrap_err().0, ErrorKind::JwksFetch(_)));
+    drop(listener);
+  }
+}
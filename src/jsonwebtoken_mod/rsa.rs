@@ -0,0 +1,233 @@
+use crate::errors::{ErrorKind, Result};
+use crate::jsonwebtoken_mod::algorithm::Algorithm;
+use crate::jsonwebtoken_mod::crypto::{b64_encode, DecodingKey, EncodingKey};
+use crate::jsonwebtoken_mod::jwk::Jwk;
+use crate::jsonwebtoken_mod::key_material::{pem, KeyMaterial};
+use crate::jsonwebtoken_mod::key_pair::KeyPair;
+use crate::jsonwebtoken_mod::public_key::PublicKey;
+use rand_core::OsRng;
+use rsa::pkcs1::der::Decode;
+use rsa::pkcs1::{DecodeRsaPrivateKey, EncodeRsaPrivateKey, EncodeRsaPublicKey};
+use rsa::pkcs8::{
+  DecodePrivateKey, Document, EncodePrivateKey, LineEnding, SubjectPublicKeyInfoRef,
+};
+use rsa::traits::{PrivateKeyParts, PublicKeyParts};
+use rsa::{BigUint, Pkcs1v15Sign, Pss, RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+/// The largest modulus a public key may have, `rsa` stops at 4096 bits by default
+const MAX_MODULUS_BITS: usize = 16384;
+
+/// The largest modulus ring signs with, and so `generateKeyPair` makes
+const MAX_SIGNING_MODULUS_BITS: usize = 4096;
+
+/// The smallest modulus RFC 7518 section 3.3 allows, and ring takes
+pub const MIN_MODULUS_BITS: usize = 2048;
+
+/// An RSA private key from a PEM, or an RSA JWK with `d`.
+/// A modulus under 2048 bits is left to `rsa`, ring refuses it
+pub fn encoding_key(key: &KeyMaterial) -> Result<EncodingKey> {
+  let private_key = match key {
+    KeyMaterial::Bytes(bytes) => match private_key(key) {
+      Ok(private_key) if private_key.n().bits() < MIN_MODULUS_BITS => {
+        return Ok(EncodingKey::Rsa(private_key))
+      }
+      _ => {
+        return Ok(EncodingKey::Jwt(jsonwebtoken::EncodingKey::from_rsa_pem(
+          bytes,
+        )?))
+      }
+    },
+    KeyMaterial::Jwk(_) => private_key(key)?,
+  };
+  if private_key.n().bits() < MIN_MODULUS_BITS {
+    return Ok(EncodingKey::Rsa(private_key));
+  }
+
+  // ring reads PKCS#1
+  let der = private_key
+    .to_pkcs1_der()
+    .map_err(|_| ErrorKind::InvalidKey)?;
+  Ok(EncodingKey::Jwt(jsonwebtoken::EncodingKey::from_rsa_der(
+    der.as_bytes(),
+  )))
+}
+
+/// An RSA public key from a PEM, or an RSA JWK.
+/// A modulus under 2048 bits is left to `rsa`, ring refuses it
+pub fn decoding_key(key: &KeyMaterial) -> Result<DecodingKey> {
+  let public_key = public_key(key)?;
+  if public_key.n().bits() < MIN_MODULUS_BITS {
+    return Ok(DecodingKey::Rsa(public_key));
+  }
+
+  let der = public_key
+    .to_pkcs1_der()
+    .map_err(|_| ErrorKind::InvalidKey)?;
+  Ok(DecodingKey::Jwt(
+    jsonwebtoken::DecodingKey::from_rsa_der(der.as_bytes()).into_static(),
+  ))
+}
+
+/// An RSA public key from a PKCS#8, PKCS#1 or SPKI PEM, or an RSA JWK
+pub fn public_key(key: &KeyMaterial) -> Result<RsaPublicKey> {
+  let bytes = match key {
+    KeyMaterial::Bytes(bytes) => bytes,
+    KeyMaterial::Jwk(jwk) => return jwk_public_key(jwk),
+  };
+
+  let pem = pem(bytes)?;
+  if let Some(public_key) = pem_public_key(pem) {
+    return Ok(public_key);
+  }
+  RsaPrivateKey::from_pkcs8_pem(pem)
+    .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+    .map(|key| key.to_public_key())
+    .map_err(|_| ErrorKind::InvalidKey.into())
+}
+
+/// A new key pair with a modulus of `bits`
+pub fn generate_key_pair(alg: Algorithm, bits: usize) -> Result<KeyPair> {
+  if !(MIN_MODULUS_BITS..=MAX_SIGNING_MODULUS_BITS).contains(&bits) || !bits.is_multiple_of(8) {
+    bail!(ErrorKind::InvalidOption(
+      "modulusLength",
+      format!(
+        "must be a multiple of 8 from 2048 to {}",
+        MAX_SIGNING_MODULUS_BITS
+      )
+    ));
+  }
+
+  let private_key = RsaPrivateKey::new(&mut OsRng, bits).map_err(|_| ErrorKind::InvalidKey)?;
+  let pem = private_key
+    .to_pkcs8_pem(LineEnding::LF)
+    .map_err(|_| ErrorKind::InvalidKey)?;
+  let member = |uint: Option<&BigUint>| uint.map(|uint| b64_encode(&uint.to_bytes_be()));
+  let primes = private_key.primes();
+  let private_members = Jwk {
+    d: member(Some(private_key.d())),
+    p: member(primes.first()),
+    q: member(primes.get(1)),
+    dp: member(private_key.dp()),
+    dq: member(private_key.dq()),
+    qi: member(private_key.crt_coefficient().as_ref()),
+    ..Jwk::default()
+  };
+
+  KeyPair::new(
+    alg,
+    pem.to_string(),
+    PublicKey::Rsa(private_key.to_public_key()),
+    private_members,
+  )
+}
+
+/// Signs with a key ring refuses, only `allowInsecureKeySizes` lets one through
+pub fn sign(key: &RsaPrivateKey, alg: Algorithm, message: &[u8]) -> Result<Vec<u8>> {
+  let signature = match alg {
+    Algorithm::RS256 => key.sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(message)),
+    Algorithm::RS384 => key.sign(Pkcs1v15Sign::new::<Sha384>(), &Sha384::digest(message)),
+    Algorithm::RS512 => key.sign(Pkcs1v15Sign::new::<Sha512>(), &Sha512::digest(message)),
+    Algorithm::PS256 => {
+      key.sign_with_rng(&mut OsRng, Pss::new::<Sha256>(), &Sha256::digest(message))
+    }
+    Algorithm::PS384 => {
+      key.sign_with_rng(&mut OsRng, Pss::new::<Sha384>(), &Sha384::digest(message))
+    }
+    Algorithm::PS512 => {
+      key.sign_with_rng(&mut OsRng, Pss::new::<Sha512>(), &Sha512::digest(message))
+    }
+    _ => bail!(ErrorKind::InvalidAlgorithm),
+  };
+
+  signature.map_err(|_| ErrorKind::InvalidKey.into())
+}
+
+/// Verifies with a key ring refuses, only `allowInsecureKeySizes` lets one through
+pub fn verify(
+  key: &RsaPublicKey,
+  alg: Algorithm,
+  message: &[u8],
+  signature: &[u8],
+) -> Result<bool> {
+  let result = match alg {
+    Algorithm::RS256 => key.verify(
+      Pkcs1v15Sign::new::<Sha256>(),
+      &Sha256::digest(message),
+      signature,
+    ),
+    Algorithm::RS384 => key.verify(
+      Pkcs1v15Sign::new::<Sha384>(),
+      &Sha384::digest(message),
+      signature,
+    ),
+    Algorithm::RS512 => key.verify(
+      Pkcs1v15Sign::new::<Sha512>(),
+      &Sha512::digest(message),
+      signature,
+    ),
+    Algorithm::PS256 => key.verify(Pss::new::<Sha256>(), &Sha256::digest(message), signature),
+    Algorithm::PS384 => key.verify(Pss::new::<Sha384>(), &Sha384::digest(message), signature),
+    Algorithm::PS512 => key.verify(Pss::new::<Sha512>(), &Sha512::digest(message), signature),
+    _ => bail!(ErrorKind::InvalidAlgorithm),
+  };
+
+  Ok(result.is_ok())
+}
+
+/// An RSA private key from a PKCS#8 or PKCS#1 PEM, or an RSA JWK with `d`
+fn private_key(key: &KeyMaterial) -> Result<RsaPrivateKey> {
+  let jwk = match key {
+    KeyMaterial::Bytes(bytes) => {
+      let pem = pem(bytes)?;
+      return RsaPrivateKey::from_pkcs8_pem(pem)
+        .or_else(|_| RsaPrivateKey::from_pkcs1_pem(pem))
+        .map_err(|_| ErrorKind::InvalidKey.into());
+    }
+    KeyMaterial::Jwk(jwk) => jwk,
+  };
+
+  // `p` and `q` are optional, `rsa` recovers them from `d`
+  let primes = match (&jwk.p, &jwk.q) {
+    (Some(_), Some(_)) => vec![uint(&jwk.p)?, uint(&jwk.q)?],
+    _ => vec![],
+  };
+  RsaPrivateKey::from_components(uint(&jwk.n)?, uint(&jwk.e)?, uint(&jwk.d)?, primes)
+    .map_err(|_| ErrorKind::InvalidKey.into())
+}
+
+fn jwk_public_key(jwk: &Jwk) -> Result<RsaPublicKey> {
+  RsaPublicKey::new_with_max_size(uint(&jwk.n)?, uint(&jwk.e)?, MAX_MODULUS_BITS)
+    .map_err(|_| ErrorKind::InvalidKey.into())
+}
+
+/// An RSA public key from an SPKI or PKCS#1 PEM.
+/// `rsa` reads these up to 4096 bits only, so the DER is read here
+fn pem_public_key(pem: &str) -> Option<RsaPublicKey> {
+  let (label, document) = Document::from_pem(pem).ok()?;
+  let der = match label {
+    "PUBLIC KEY" => {
+      let spki = SubjectPublicKeyInfoRef::from_der(document.as_bytes()).ok()?;
+      spki
+        .algorithm
+        .assert_algorithm_oid(rsa::pkcs1::ALGORITHM_OID)
+        .ok()?;
+      spki.subject_public_key.as_bytes()?
+    }
+    "RSA PUBLIC KEY" => document.as_bytes(),
+    _ => return None,
+  };
+
+  let key = rsa::pkcs1::RsaPublicKey::from_der(der).ok()?;
+  RsaPublicKey::new_with_max_size(
+    BigUint::from_bytes_be(key.modulus.as_bytes()),
+    BigUint::from_bytes_be(key.public_exponent.as_bytes()),
+    MAX_MODULUS_BITS,
+  )
+  .ok()
+}
+
+/// A big-endian integer member of the key
+fn uint(member: &Option<String>) -> Result<BigUint> {
+  Ok(BigUint::from_bytes_be(&Jwk::decode_member(member)?))
+}
@@ -0,0 +1,190 @@
+//! Defines the JWK functions besides `sign` and `verify`
+
+use crate::errors::{ErrorKind, OrThrow, Result};
+use crate::jsonwebtoken_mod::algorithm::{Algorithm, AsStr, Key};
+use crate::jsonwebtoken_mod::jwk::{Jwk, JwkSet, ThumbprintHash};
+use crate::jsonwebtoken_mod::key_material::KeyMaterial;
+use crate::jsonwebtoken_mod::public_key::PublicKey;
+use crate::jsonwebtoken_mod::remote_jwks::{RemoteJwks, RemoteJwksOptions};
+use crate::neon_serde;
+use crate::utils::{key_argument, key_value, parse_options};
+use neon::prelude::*;
+use std::sync::Arc;
+
+impl Finalize for RemoteJwks {}
+
+/// A JWK Set fetched from `url` when `verifyAsync` first needs it,
+/// `index.js` wraps the box in a `RemoteJwks`
+pub fn create_remote_jwks(mut cx: FunctionContext) -> JsResult<JsBox<Arc<RemoteJwks>>> {
+  let url = cx.argument::<JsString>(0)?.value(&mut cx);
+  let options = cx.argument_opt(1);
+  let options: RemoteJwksOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+
+  let remote = RemoteJwks::new(&url, options).or_throw(&mut cx)?;
+
+  Ok(cx.boxed(Arc::new(remote)))
+}
+
+/// The public JWK of a private or public key
+pub fn export_jwk(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let key = required_key_argument(&mut cx, 0)?;
+  let options = cx.argument_opt(1);
+  let options: ExportOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+
+  let jwk = options.export(&key, false).or_throw(&mut cx)?;
+
+  neon_serde::to_value(&mut cx, &jwk).or_throw(&mut cx)
+}
+
+/// The SPKI PEM of a private or public key
+pub fn export_public_pem(mut cx: FunctionContext) -> JsResult<JsString> {
+  let key = required_key_argument(&mut cx, 0)?;
+
+  let pem = PublicKey::new(&key)
+    .and_then(|public_key| public_key.to_pem())
+    .or_throw(&mut cx)?;
+
+  Ok(cx.string(pem))
+}
+
+/// A JWK Set of the public keys of `[key, options]` pairs, to publish for `verify`.
+/// A key without a `kid` gets its thumbprint
+pub fn to_jwks(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let entries = cx.argument::<JsArray>(0)?.to_vec(&mut cx)?;
+
+  let mut keys = Vec::with_capacity(entries.len());
+  for entry in entries {
+    let entry = entry.downcast_or_throw::<JsArray, _>(&mut cx)?;
+    let key = entry.get(&mut cx, 0)?;
+    let key = match key_value(&mut cx, key, "key")? {
+      Some(key) => key,
+      None => return missing_key(&mut cx),
+    };
+    let options = entry.get(&mut cx, 1)?;
+    let options: ExportOptions = parse_options(&mut cx, Some(options)).or_throw(&mut cx)?;
+
+    keys.push(options.export(&key, true).or_throw(&mut cx)?);
+  }
+
+  neon_serde::to_value(&mut cx, &JwkSet { keys }).or_throw(&mut cx)
+}
+
+/// The RFC 7638 thumbprint of a key, by `sha256` unless `hash` is `sha384` or `sha512`
+pub fn jwk_thumbprint(mut cx: FunctionContext) -> JsResult<JsString> {
+  let key = required_key_argument(&mut cx, 0)?;
+  let hash = match cx.argument_opt(1) {
+    Some(hash) if !hash.is_a::<JsUndefined, _>(&mut cx) => {
+      let hash = hash
+        .downcast_or_throw::<JsString, _>(&mut cx)?
+        .value(&mut cx);
+      match ThumbprintHash::from_name(&hash) {
+        Some(hash) => hash,
+        None => {
+          return crate::errors::Error::from(ErrorKind::InvalidArgument(
+            "hash",
+            "must be \"sha256\", \"sha384\" or \"sha512\"",
+          ))
+          .throw(&mut cx)
+        }
+      }
+    }
+    _ => ThumbprintHash::default(),
+  };
+
+  let thumbprint = key.thumbprint(hash).or_throw(&mut cx)?;
+
+  Ok(cx.string(thumbprint))
+}
+
+/// A new key pair for `alg`, as PEMs and JWKs
+pub fn generate_key_pair(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let alg = cx.argument::<JsValue>(0)?;
+  let alg: Algorithm = neon_serde::from_value(&mut cx, alg).or_throw(&mut cx)?;
+  let options = cx.argument_opt(1);
+  let options: KeyPairOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+
+  let key_pair = alg
+    .generate_key_pair(options.modulus_length)
+    .or_throw(&mut cx)?;
+
+  neon_serde::to_value(&mut cx, &key_pair).or_throw(&mut cx)
+}
+
+/// A new secret for the HMAC `alg`
+pub fn generate_secret(mut cx: FunctionContext) -> JsResult<JsBuffer> {
+  let alg = cx.argument::<JsValue>(0)?;
+  let alg: Algorithm = neon_serde::from_value(&mut cx, alg).or_throw(&mut cx)?;
+
+  let secret = alg.generate_secret().or_throw(&mut cx)?;
+
+  let mut buffer = JsBuffer::new(&mut cx, secret.len() as u32)?;
+  cx.borrow_mut(&mut buffer, |buffer| {
+    buffer.as_mut_slice().copy_from_slice(&secret)
+  });
+  Ok(buffer)
+}
+
+fn required_key_argument(cx: &mut FunctionContext, index: i32) -> NeonResult<KeyMaterial> {
+  match key_argument(cx, index, "key")? {
+    Some(key) => Ok(key),
+    None => missing_key(cx),
+  }
+}
+
+fn missing_key<'a, C: Context<'a>, T>(cx: &mut C) -> NeonResult<T> {
+  crate::errors::Error::from(ErrorKind::InvalidArgument("key", "must not be null")).throw(cx)
+}
+
+/// The options of `exportJwk` and of each key of `toJwks`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ExportOptions {
+  kid: Option<String>,
+  #[serde(rename = "use")]
+  use_: Option<String>,
+  alg: Option<Algorithm>,
+}
+
+/// The options of `generateKeyPair`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct KeyPairOptions {
+  /// in bits, 2048 by default
+  modulus_length: Option<usize>,
+}
+
+impl ExportOptions {
+  /// The public JWK of `key` with the `kid`, `use` and `alg` of the options,
+  /// else those of `key` when it is a JWK, else the thumbprint for `kid` when `thumbprint_kid`
+  fn export(&self, key: &KeyMaterial, thumbprint_kid: bool) -> Result<Jwk> {
+    let mut jwk = PublicKey::new(key)?.to_jwk();
+
+    if let Some(alg) = self.alg {
+      if !jwk.fits(alg) {
+        let reason = format!("\"{}\" does not fit the key", alg.as_str());
+        bail!(ErrorKind::InvalidOption("alg", reason));
+      }
+    }
+
+    let source = match key {
+      KeyMaterial::Jwk(source) => Some(source),
+      KeyMaterial::Bytes(_) => None,
+    };
+    let member = |option: &Option<String>, member: fn(&Jwk) -> &Option<String>| {
+      option
+        .clone()
+        .or_else(|| source.and_then(|source| member(source).clone()))
+    };
+    jwk.kid = member(&self.kid, |jwk| &jwk.kid);
+    jwk.use_ = member(&self.use_, |jwk| &jwk.use_);
+    jwk.alg = member(&self.alg.map(|alg| alg.as_str().to_string()), |jwk| {
+      &jwk.alg
+    });
+
+    if jwk.kid.is_none() && thumbprint_kid {
+      jwk.kid = Some(jwk.thumbprint(ThumbprintHash::Sha256)?);
+    }
+
+    Ok(jwk)
+  }
+}
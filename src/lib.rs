@@ -1,3 +1,6 @@
+// `error_chain!` expands one level per error kind
+#![recursion_limit = "256"]
+
 #[macro_use]
 extern crate error_chain;
 extern crate neon;
@@ -8,10 +11,24 @@ extern crate serde;
 use neon::prelude::*;
 
 mod decode;
+// `error_chain!` expands to a `cfg` rustc does not know
+#[allow(unexpected_cfgs)]
+mod errors;
 mod jsonwebtoken_mod;
+mod jwk;
+// vendored, kept as upstream wrote it
+#[allow(
+    renamed_and_removed_lints,
+    unused_attributes,
+    unexpected_cfgs,
+    clippy::all
+)]
 mod neon_serde;
 mod sign;
+mod task;
+mod timespan;
 mod utils;
+mod validation;
 mod verify;
 
 #[neon::main]
@@ -19,5 +36,20 @@ fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("sign", sign::sign)?;
     cx.export_function("decode", decode::decode)?;
     cx.export_function("verify", verify::verify)?;
+    cx.export_function("signAsync", sign::sign_async)?;
+    cx.export_function("verifyAsync", verify::verify_async)?;
+    cx.export_function("signMany", sign::sign_many)?;
+    cx.export_function("verifyMany", verify::verify_many)?;
+    cx.export_function("createSigner", sign::create_signer)?;
+    cx.export_function("signerSign", sign::signer_sign)?;
+    cx.export_function("createVerifier", verify::create_verifier)?;
+    cx.export_function("verifierVerify", verify::verifier_verify)?;
+    cx.export_function("createRemoteJwks", jwk::create_remote_jwks)?;
+    cx.export_function("exportJwk", jwk::export_jwk)?;
+    cx.export_function("exportPublicPem", jwk::export_public_pem)?;
+    cx.export_function("toJwks", jwk::to_jwks)?;
+    cx.export_function("jwkThumbprint", jwk::jwk_thumbprint)?;
+    cx.export_function("generateKeyPair", jwk::generate_key_pair)?;
+    cx.export_function("generateSecret", jwk::generate_secret)?;
     Ok(())
 }
@@ -1,39 +1,145 @@
-use crate::jsonwebtoken_mod::algorithm::Key;
+use crate::errors::{ErrorKind, OrThrow, Result};
+use crate::jsonwebtoken_mod::algorithm::{Algorithm, Key};
+use crate::jsonwebtoken_mod::crypto::EncodingKey;
+use crate::jsonwebtoken_mod::encoding::encode;
+use crate::jsonwebtoken_mod::header::JoseHeader;
+use crate::jsonwebtoken_mod::jwk::ThumbprintHash;
+use crate::jsonwebtoken_mod::key_material::{KeyMaterial, RawKey};
 use crate::neon_serde;
-use crate::utils::now;
-use jsonwebtoken::{encode, Algorithm, Header};
+use crate::task::spawn;
+use crate::timespan::{Seconds, TimeSpan};
+use crate::utils::{
+  key_argument, now, parse_options, raw_key_argument, to_results_array, BatchOptions, OneOrMany,
+};
 use neon::prelude::*;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
+use serde_json::{Map, Value};
 
 pub fn sign(mut cx: FunctionContext) -> JsResult<JsString> {
   let payload = cx.argument::<JsValue>(0)?;
-  let key = cx.argument::<JsString>(1)?.value(&mut cx);
+  let key = raw_key_argument(&mut cx, 1, "secretOrPrivateKey")?;
   let options = cx.argument_opt(2);
 
-  let mut claims: Claims = neon_serde::from_value(&mut cx, payload).unwrap();
-  let sign_options: SignOptions =
-    neon_serde::from_value_opt(&mut cx, options).unwrap_or(SignOptions::default());
-  sign_options.parse_options(&mut claims);
+  let claims: Claims = neon_serde::from_value(&mut cx, payload).or_throw(&mut cx)?;
+  let sign_options: SignOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
 
-  let encoding_key = sign_options
-    .header
-    .unwrap_or(Header::default())
-    .alg
-    .get_encoding_key(key.as_bytes());
+  let token = sign_claims(key, sign_options, claims).or_throw(&mut cx)?;
 
-  let token = encode(&Header::default(), &claims, &encoding_key).unwrap();
+  Ok(cx.string(token))
+}
+
+/// Like `sign`, but parses the key and signs on the thread pool,
+/// then calls back `callback(error, token)`
+pub fn sign_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let payload = cx.argument::<JsValue>(0)?;
+  let key = raw_key_argument(&mut cx, 1, "secretOrPrivateKey")?;
+  let options = cx.argument_opt(2);
+  let callback = cx.argument::<JsFunction>(3)?;
+
+  let claims: Claims = neon_serde::from_value(&mut cx, payload).or_throw(&mut cx)?;
+  let sign_options: SignOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+
+  spawn(
+    &mut cx,
+    callback,
+    move || sign_claims(key, sign_options, claims),
+    |cx, token| Ok(cx.string(token).upcast()),
+  );
+
+  Ok(cx.undefined())
+}
+
+/// What `sign` and `signAsync` do once the arguments are read, on either thread
+fn sign_claims(key: Option<RawKey>, options: SignOptions, claims: Claims) -> Result<String> {
+  let key = key.map(RawKey::parse).transpose()?;
+  Signer::new(key.as_ref(), options)?.sign(claims)
+}
+
+/// Signs every payload with the key and options parsed once,
+/// a payload that can not be signed gives its error in place of the token
+pub fn sign_many(mut cx: FunctionContext) -> JsResult<JsArray> {
+  let payloads = cx.argument::<JsArray>(0)?.to_vec(&mut cx)?;
+  let key = key_argument(&mut cx, 1, "secretOrPrivateKey")?;
+  let options = cx.argument_opt(2);
+
+  let sign_options: SignOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+  let batch_options: BatchOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+  let signer = Signer::new(key.as_ref(), sign_options).or_throw(&mut cx)?;
+
+  let claims: Vec<Result<Claims>> = payloads
+    .into_iter()
+    .map(|payload| Ok(neon_serde::from_value(&mut cx, payload)?))
+    .collect();
+  let tokens = batch_options.map(claims, |claims| signer.sign(claims?));
+
+  to_results_array(&mut cx, tokens, |cx, token| Ok(cx.string(token).upcast()))
+}
+
+/// A key, options and header parsed once, `index.js` wraps the box in a `Signer`
+pub struct Signer {
+  options: SignOptions,
+  header: JoseHeader,
+  key: EncodingKey,
+}
+
+impl Finalize for Signer {}
+
+impl Signer {
+  /// `key` is `None` for an unsecured token
+  fn new(key: Option<&KeyMaterial>, options: SignOptions) -> Result<Self> {
+    let header = options.to_header(options.algorithm(key.is_none())?, key)?;
+    let key = match key {
+      Some(key) => {
+        let encoding_key = header.alg.get_encoding_key(key)?;
+        if !options.allow_insecure_key_sizes.unwrap_or(false) {
+          header.alg.check_key_strength(key)?;
+        }
+        encoding_key
+      }
+      None => EncodingKey::Unsecured,
+    };
+
+    Ok(Signer {
+      options,
+      header,
+      key,
+    })
+  }
+
+  fn sign(&self, mut claims: Claims) -> Result<String> {
+    self.options.parse_options(&mut claims)?;
+    encode(&self.header, &claims, &self.key)
+  }
+}
+
+pub fn create_signer(mut cx: FunctionContext) -> JsResult<JsBox<Signer>> {
+  let key = key_argument(&mut cx, 0, "secretOrPrivateKey")?;
+  let options = cx.argument_opt(1);
+
+  let sign_options: SignOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+  let signer = Signer::new(key.as_ref(), sign_options).or_throw(&mut cx)?;
+
+  Ok(cx.boxed(signer))
+}
+
+pub fn signer_sign(mut cx: FunctionContext) -> JsResult<JsString> {
+  let signer = cx.argument::<JsBox<Signer>>(0)?;
+  let payload = cx.argument::<JsValue>(1)?;
+
+  let claims: Claims = neon_serde::from_value(&mut cx, payload).or_throw(&mut cx)?;
+  let token = signer.sign(claims).or_throw(&mut cx)?;
 
   Ok(cx.string(token))
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Claims {
-  #[serde(default = "now")]
-  iat: u64,
+  /// when given, the base of `expiresIn` and `notBefore` instead of now
+  #[serde(skip_serializing_if = "Option::is_none")]
+  iat: Option<Seconds>,
+  /// ordered, so the same payload always signs to the same token
   #[serde(flatten)]
-  extra: HashMap<String, Value>,
+  extra: Map<String, Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -43,52 +149,121 @@ struct SignOptions {
   algorithm: Option<Algorithm>,
   /// expressed in seconds or a string describing a time span vercel/ms.
   /// Eg: 60, "2 days", "10h", "7d". A numeric value is interpreted as a seconds count. If you use a string be sure you provide the time units (days, hours, etc), otherwise milliseconds unit is used by default ("120" is equal to "120ms").
-  expires_in: Option<u64>,
+  expires_in: Option<TimeSpan>,
 
   /// expressed in seconds or a string describing a time span vercel/ms.
   /// Eg: 60, "2 days", "10h", "7d". A numeric value is interpreted as a seconds count. If you use a string be sure you provide the time units (days, hours, etc), otherwise milliseconds unit is used by default ("120" is equal to "120ms").
-  not_before: Option<u64>,
-  audience: Option<String>,
+  not_before: Option<TimeSpan>,
+  audience: Option<OneOrMany<String>>,
   issuer: Option<String>,
   jwtid: Option<String>,
   subject: Option<String>,
   no_timestamp: Option<bool>,
-  header: Option<Header>,
+  /// merged over the generated `alg`, `typ` and `kid`, extra members are kept
+  header: Option<Map<String, Value>>,
   keyid: Option<String>,
+  /// sign with `alg: none` when the key is `null`, never implied
+  allow_unsigned: Option<bool>,
+  /// accept secrets shorter than the hash output and RSA moduli under 2048 bits, for legacy keys
+  allow_insecure_key_sizes: Option<bool>,
 }
 
 impl SignOptions {
-  fn insert_claim(&self, claims: &mut Claims, key: &str, value: &Option<String>) {
-    if value.is_some() {
+  fn insert_claim<T: Serialize>(
+    &self,
+    claims: &mut Claims,
+    option: &'static str,
+    key: &'static str,
+    value: &Option<T>,
+  ) -> Result<()> {
+    if let Some(value) = value {
+      if claims.extra.contains_key(key) {
+        bail!(ErrorKind::ClaimConflict(option, key));
+      }
+      claims
+        .extra
+        .insert(key.to_string(), serde_json::to_value(value)?);
+    }
+    Ok(())
+  }
+  fn insert_claim_timespan(
+    &self,
+    claims: &mut Claims,
+    option: &'static str,
+    key: &'static str,
+    value: Option<TimeSpan>,
+    timestamp: u64,
+  ) -> Result<()> {
+    if let Some(value) = value {
+      if claims.extra.contains_key(key) {
+        bail!(ErrorKind::ClaimConflict(option, key));
+      }
       claims.extra.insert(
         key.to_string(),
-        serde_json::Value::from(value.as_ref().unwrap().to_string()),
+        serde_json::Value::from(value.after(timestamp)),
       );
     }
+    Ok(())
   }
-  fn insert_claim_u64(&self, claims: &mut Claims, key: &str, value: Option<u64>) {
-    if value.is_some() {
-      claims
-        .extra
-        .insert(key.to_string(), serde_json::Value::from(value.unwrap()));
+
+  fn parse_options(&self, claims: &mut Claims) -> Result<()> {
+    let timestamp = claims.iat.map_or_else(now, |iat| iat.0);
+    claims.iat = if self.no_timestamp.unwrap_or(false) {
+      None
+    } else {
+      Some(Seconds(timestamp))
+    };
+
+    self.insert_claim_timespan(claims, "expiresIn", "exp", self.expires_in, timestamp)?;
+    self.insert_claim_timespan(claims, "notBefore", "nbf", self.not_before, timestamp)?;
+    self.insert_claim(claims, "audience", "aud", &self.audience)?;
+    self.insert_claim(claims, "issuer", "iss", &self.issuer)?;
+    self.insert_claim(claims, "jwtid", "jti", &self.jwtid)?;
+    self.insert_claim(claims, "subject", "sub", &self.subject)
+  }
+
+  /// The `alg` to sign with, `none` takes both a `null` key and `allowUnsigned`
+  fn algorithm(&self, unsigned: bool) -> Result<Algorithm> {
+    let allow_unsigned = self.allow_unsigned.unwrap_or(false);
+    match (self.algorithm, unsigned) {
+      (None, true) | (Some(Algorithm::None), true) if allow_unsigned => Ok(Algorithm::None),
+      (_, true) if !allow_unsigned => bail!(ErrorKind::InvalidArgument(
+        "secretOrPrivateKey",
+        "must not be null unless options.allowUnsigned is set"
+      )),
+      (_, true) => bail!(ErrorKind::InvalidOption(
+        "algorithm",
+        "must be \"none\" when the key is null".to_string()
+      )),
+      (Some(Algorithm::None), false) => bail!(ErrorKind::InvalidOption(
+        "algorithm",
+        "\"none\" takes a null key and options.allowUnsigned".to_string()
+      )),
+      (algorithm, false) => Ok(algorithm.unwrap_or(Algorithm::HS256)),
     }
   }
 
-  fn parse_options(&self, claims: &mut Claims) {
-    self.insert_claim_u64(
-      claims,
-      "exp",
-      self.expires_in.and_then(|exp| Some(exp + now())),
-    );
-    self.insert_claim_u64(
-      claims,
-      "nbf",
-      self.not_before.and_then(|nbf| Some(nbf + now())),
-    );
-    self.insert_claim(claims, "aud", &self.audience);
-    self.insert_claim(claims, "iss", &self.issuer);
-    self.insert_claim(claims, "jti", &self.jwtid);
-    self.insert_claim(claims, "sub", &self.subject);
+  /// `keyid: "thumbprint"` stamps the RFC 7638 thumbprint of `key` as the `kid`
+  fn to_header(&self, algorithm: Algorithm, key: Option<&KeyMaterial>) -> Result<JoseHeader> {
+    let mut header = Map::new();
+    header.insert("alg".to_string(), serde_json::to_value(algorithm)?);
+    header.insert("typ".to_string(), Value::from("JWT"));
+    let keyid = match (self.keyid.as_deref(), key) {
+      (Some("thumbprint"), Some(key)) => Some(key.thumbprint(ThumbprintHash::Sha256)?),
+      (Some("thumbprint"), None) => bail!(ErrorKind::InvalidOption(
+        "keyid",
+        "\"thumbprint\" needs a key".to_string()
+      )),
+      (keyid, _) => keyid.map(String::from),
+    };
+    if let Some(keyid) = keyid {
+      header.insert("kid".to_string(), Value::from(keyid));
+    }
+    if let Some(extra) = &self.header {
+      header.extend(extra.clone());
+    }
+
+    Ok(serde_json::from_value(Value::Object(header))?)
   }
 }
 
@@ -105,6 +280,236 @@ impl Default for SignOptions {
       no_timestamp: None,
       header: None,
       keyid: None,
+      allow_unsigned: None,
+      allow_insecure_key_sizes: None,
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::jsonwebtoken_mod::crypto::b64_decode;
+  use crate::task::Runner;
+  use ::rsa::pkcs8::{EncodePublicKey, LineEnding};
+  use serde_json::json;
+
+  fn sign_options(options: Value) -> SignOptions {
+    serde_json::from_value(options).unwrap()
+  }
+
+  fn claims(payload: Value) -> Claims {
+    serde_json::from_value(payload).unwrap()
+  }
+
+  fn signed_claims(options: Value, payload: Value) -> Result<Value> {
+    let mut claims = claims(payload);
+    sign_options(options).parse_options(&mut claims)?;
+    Ok(serde_json::to_value(claims)?)
+  }
+
+  #[test]
+  fn test_timestamp() {
+    let claims = signed_claims(json!({}), json!({})).unwrap();
+    let iat = claims["iat"].as_u64().unwrap();
+    assert!(iat.abs_diff(now()) <= 1);
+
+    let claims = signed_claims(json!({"noTimestamp": true}), json!({"a": 1})).unwrap();
+    assert_eq!(claims, json!({"a": 1}));
+  }
+
+  #[test]
+  fn test_payload_iat_is_the_base_of_exp_and_nbf() {
+    let options = json!({"expiresIn": "1h", "notBefore": 60});
+    let claims = signed_claims(options.clone(), json!({"iat": 1000})).unwrap();
+    assert_eq!(claims, json!({"iat": 1000, "exp": 4600, "nbf": 1060}));
+
+    // like `Date.now() / 1000`
+    let claims = signed_claims(options, json!({"iat": 1000.75})).unwrap();
+    assert_eq!(claims, json!({"iat": 1000, "exp": 4600, "nbf": 1060}));
+
+    let options = json!({"expiresIn": 60, "noTimestamp": true});
+    let claims = signed_claims(options, json!({"iat": 1000})).unwrap();
+    assert_eq!(claims, json!({"exp": 1060}));
+  }
+
+  #[test]
+  fn test_audience_and_issuer() {
+    let options = json!({"audience": ["urn:a", "urn:b"], "issuer": "a", "noTimestamp": true});
+    let claims = signed_claims(options, json!({})).unwrap();
+    assert_eq!(claims, json!({"aud": ["urn:a", "urn:b"], "iss": "a"}));
+
+    let options = json!({"audience": "urn:a", "noTimestamp": true});
+    let claims = signed_claims(options, json!({})).unwrap();
+    assert_eq!(claims, json!({"aud": "urn:a"}));
+  }
+
+  #[test]
+  fn test_claim_conflict() {
+    let error = signed_claims(json!({"expiresIn": 60}), json!({"exp": 1})).unwrap_err();
+    assert!(matches!(
+      error.kind(),
+      ErrorKind::ClaimConflict("expiresIn", "exp")
+    ));
+    let error = signed_claims(json!({"notBefore": 60}), json!({"nbf": 1})).unwrap_err();
+    assert!(matches!(
+      error.kind(),
+      ErrorKind::ClaimConflict("notBefore", "nbf")
+    ));
+
+    for (option, claim) in [
+      ("audience", "aud"),
+      ("issuer", "iss"),
+      ("jwtid", "jti"),
+      ("subject", "sub"),
+    ] {
+      let error = signed_claims(json!({ option: "a" }), json!({ claim: "b" })).unwrap_err();
+      assert_eq!(
+        error.to_string(),
+        format!(
+          "Bad \"options.{}\" option the payload already has an \"{}\" property.",
+          option, claim
+        )
+      );
+      assert!(signed_claims(json!({ option: "a" }), json!({})).is_ok());
+    }
+  }
+
+  #[test]
+  fn test_sign_on_thread_pool() {
+    let key = |bytes: &[u8]| Some(RawKey::Bytes(bytes.to_vec(), None));
+    let options = || sign_options(json!({"expiresIn": 60}));
+    let payload = || claims(json!({"iat": 1000, "sub": "a"}));
+    let token = sign_claims(key(&[7; 32]), options(), payload()).unwrap();
+    // decrypted by the job, so a wrong passphrase only fails there
+    let private_key = Algorithm::ES256
+      .generate_key_pair(None)
+      .unwrap()
+      .private_key;
+    let private_key = pkcs8::der::Document::from_pem(&private_key.pem).unwrap().1;
+    let encrypted = <pkcs8::PrivateKeyInfo as pkcs8::der::Decode>::from_der(private_key.as_bytes())
+      .unwrap()
+      // one PBKDF2 iteration, as the default scrypt is slow without optimizations
+      .encrypt_with_params(
+        pkcs8::pkcs5::pbes2::Parameters::pbkdf2_sha256_aes256cbc(1, &[7; 16], &[7; 16]).unwrap(),
+        "passphrase",
+      )
+      .unwrap();
+    let encrypted = || {
+      Some(RawKey::Bytes(
+        encrypted.as_bytes().to_vec(),
+        Some(b"wrong".to_vec()),
+      ))
+    };
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    for key in [key(&[7; 32]), key(&[7; 8]), encrypted()] {
+      let sender = sender.clone();
+      let (options, payload) = (options(), payload());
+      Runner::Pool.run(move || sender.send(sign_claims(key, options, payload)).unwrap());
+    }
+    let results: Vec<Result<String>> = receiver.iter().take(3).collect();
+
+    let tokens = results.iter().filter_map(|result| result.as_ref().ok());
+    assert_eq!(tokens.collect::<Vec<_>>(), [&token]);
+    // errors for the callback, not panics off the main thread
+    let errors: Vec<_> = results
+      .iter()
+      .filter_map(|result| result.as_ref().err())
+      .collect();
+    let insecure = errors
+      .iter()
+      .filter(|error| matches!(error.kind(), ErrorKind::InsecureKey(..)));
+    assert_eq!(insecure.count(), 1);
+    let undecrypted = errors
+      .iter()
+      .filter(|error| matches!(error.kind(), ErrorKind::InvalidArgument("passphrase", _)));
+    assert_eq!(undecrypted.count(), 1);
+  }
+
+  #[test]
+  fn test_generated_header() {
+    let header = sign_options(json!({}))
+      .to_header(Algorithm::HS256, None)
+      .unwrap();
+    assert_eq!(
+      serde_json::to_value(header).unwrap(),
+      json!({"alg": "HS256", "typ": "JWT"})
+    );
+
+    let header = sign_options(json!({"keyid": "a"}))
+      .to_header(Algorithm::RS256, None)
+      .unwrap();
+    assert_eq!(
+      serde_json::to_value(header).unwrap(),
+      json!({"alg": "RS256", "typ": "JWT", "kid": "a"})
+    );
+  }
+
+  #[test]
+  fn test_thumbprint_keyid() {
+    // the example of RFC 7638 section 3.1
+    let n = "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw";
+    let thumbprint = "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs";
+    let jwk = KeyMaterial::Jwk(Box::new(
+      serde_json::from_value(
+        json!({"kty": "RSA", "n": n, "e": "AQAB", "alg": "RS256", "kid": "2011-04-29"}),
+      )
+      .unwrap(),
+    ));
+    let public_key = ::rsa::RsaPublicKey::new(
+      ::rsa::BigUint::from_bytes_be(&b64_decode(n).unwrap()),
+      ::rsa::BigUint::from(65537u32),
+    )
+    .unwrap();
+    let pem = KeyMaterial::Bytes(
+      public_key
+        .to_public_key_pem(LineEnding::LF)
+        .unwrap()
+        .into_bytes(),
+    );
+    let options = sign_options(json!({"keyid": "thumbprint"}));
+
+    for key in [jwk, pem] {
+      let header = options.to_header(Algorithm::RS256, Some(&key)).unwrap();
+      assert_eq!(header.kid.as_deref(), Some(thumbprint));
+    }
+
+    let error = options.to_header(Algorithm::RS256, None).unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::InvalidOption("keyid", _)));
+  }
+
+  #[test]
+  fn test_header_option_overrides_generated_members() {
+    let options = sign_options(json!({
+      "keyid": "a",
+      "header": {
+        "typ": "at+jwt",
+        "kid": "b",
+        "x5u": ["https://example.com/a.pem", "https://example.com/b.pem"],
+        "custom": {"nested": true},
+      },
+    }));
+    let header = options.to_header(Algorithm::RS256, None).unwrap();
+
+    assert_eq!(
+      serde_json::to_value(header).unwrap(),
+      json!({
+        "alg": "RS256",
+        "typ": "at+jwt",
+        "kid": "b",
+        "x5u": ["https://example.com/a.pem", "https://example.com/b.pem"],
+        "custom": {"nested": true},
+      })
+    );
+  }
+
+  #[test]
+  fn test_header_alg_overrides_algorithm() {
+    let options = sign_options(json!({"algorithm": "HS256", "header": {"alg": "HS384"}}));
+    let key = KeyMaterial::Bytes(vec![7; 48]);
+    let signer = Signer::new(Some(&key), options).unwrap();
+
+    assert_eq!(signer.header.alg, Algorithm::HS384);
+  }
+}
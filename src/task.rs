@@ -0,0 +1,84 @@
+//! Runs the crypto of `signAsync` and `verifyAsync` off the javascript main thread
+
+use crate::errors::Result;
+use crate::jsonwebtoken_mod::remote_jwks::RemoteJwks;
+use neon::prelude::*;
+use std::sync::Arc;
+
+/// When `spawn_on` runs the work
+#[derive(Debug, Clone)]
+pub enum Runner {
+  /// right away on the rayon thread pool
+  Pool,
+  /// on the rayon thread pool once the remote JWK Set is fetched,
+  /// so that no thread of the pool waits on the network
+  AfterFetch(Arc<RemoteJwks>),
+}
+
+impl Runner {
+  /// Runs `job` off the calling thread
+  pub fn run<F: FnOnce() + Send + 'static>(self, job: F) {
+    match self {
+      Runner::Pool => rayon::spawn(job),
+      Runner::AfterFetch(remote) => remote.fetch_then(move || rayon::spawn(job)),
+    }
+  }
+}
+
+/// Runs `work` on the rayon thread pool, then calls `callback(error, value)`
+/// back on the javascript main thread, with the value `to_js` makes of the result
+pub fn spawn<T, W, J>(cx: &mut FunctionContext, callback: Handle<JsFunction>, work: W, to_js: J)
+where
+  T: Send + 'static,
+  W: FnOnce() -> Result<T> + Send + 'static,
+  J: for<'a> FnOnce(&mut TaskContext<'a>, T) -> JsResult<'a, JsValue> + Send + 'static,
+{
+  spawn_on(Runner::Pool, cx, callback, work, to_js);
+}
+
+/// Like `spawn`, but runs `work` when `runner` says
+pub fn spawn_on<T, W, J>(
+  runner: Runner,
+  cx: &mut FunctionContext,
+  callback: Handle<JsFunction>,
+  work: W,
+  to_js: J,
+) where
+  T: Send + 'static,
+  W: FnOnce() -> Result<T> + Send + 'static,
+  J: for<'a> FnOnce(&mut TaskContext<'a>, T) -> JsResult<'a, JsValue> + Send + 'static,
+{
+  let done = call_back(cx, callback, to_js);
+  runner.run(move || done(work()));
+}
+
+/// Sends a result to `callback` on the javascript main thread
+fn call_back<T, J>(
+  cx: &mut FunctionContext,
+  callback: Handle<JsFunction>,
+  to_js: J,
+) -> impl FnOnce(Result<T>) + Send + 'static
+where
+  T: Send + 'static,
+  J: for<'a> FnOnce(&mut TaskContext<'a>, T) -> JsResult<'a, JsValue> + Send + 'static,
+{
+  let callback = callback.root(cx);
+  let channel = cx.channel();
+
+  move |result| {
+    channel.send(move |mut cx| {
+      let callback = callback.into_inner(&mut cx);
+      let this = cx.undefined();
+      let args: Vec<Handle<JsValue>> = match result {
+        Ok(value) => {
+          let value = to_js(&mut cx, value)?;
+          vec![cx.null().upcast(), value]
+        }
+        Err(error) => vec![error.to_js_error(&mut cx)?.upcast()],
+      };
+
+      callback.call(&mut cx, this, args)?;
+      Ok(())
+    });
+  }
+}
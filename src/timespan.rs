@@ -0,0 +1,300 @@
+//! Time spans in the format of [vercel/ms](https://github.com/vercel/ms),
+//! Eg: 60, "2 days", "10h", "7d", "1.5 hours", "-3m".
+//! A number is a count of seconds, a string without units is a count of milliseconds.
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+
+const SECOND: f64 = 1000.0;
+const MINUTE: f64 = SECOND * 60.0;
+const HOUR: f64 = MINUTE * 60.0;
+const DAY: f64 = HOUR * 24.0;
+const WEEK: f64 = DAY * 7.0;
+const YEAR: f64 = DAY * 365.25;
+
+/// Parses a time span string into milliseconds
+pub fn parse(input: &str) -> Option<f64> {
+  if input.is_empty() || input.len() > 100 {
+    return None;
+  }
+
+  let number_len = input
+    .char_indices()
+    .find(|&(index, c)| !(c.is_ascii_digit() || c == '.' || (c == '-' && index == 0)))
+    .map_or(input.len(), |(index, _)| index);
+  let (number, unit) = input.split_at(number_len);
+  let value = parse_number(number)?;
+
+  let multiplier = match unit.trim_start_matches(' ').to_ascii_lowercase().as_str() {
+    "years" | "year" | "yrs" | "yr" | "y" => YEAR,
+    "weeks" | "week" | "w" => WEEK,
+    "days" | "day" | "d" => DAY,
+    "hours" | "hour" | "hrs" | "hr" | "h" => HOUR,
+    "minutes" | "minute" | "mins" | "min" | "m" => MINUTE,
+    "seconds" | "second" | "secs" | "sec" | "s" => SECOND,
+    "milliseconds" | "millisecond" | "msecs" | "msec" | "ms" | "" => 1.0,
+    _ => return None,
+  };
+
+  Some(value * multiplier)
+}
+
+/// Accepts `-?\d*\.?\d+`, the number part of the vercel/ms grammar
+fn parse_number(number: &str) -> Option<f64> {
+  let digits = number.strip_prefix('-').unwrap_or(number);
+  let (whole, fraction) = match digits.find('.') {
+    Some(index) => (&digits[..index], &digits[index + 1..]),
+    None => ("", digits),
+  };
+
+  let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+  if fraction.is_empty() || !all_digits(whole) || !all_digits(fraction) {
+    return None;
+  }
+
+  number.parse().ok()
+}
+
+/// A signed count of seconds, deserialized from a number of seconds or a time span string
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct TimeSpan(pub i64);
+
+impl TimeSpan {
+  /// Adds the span to `timestamp`, clamped to the epoch
+  pub fn after(self, timestamp: u64) -> u64 {
+    (timestamp as i64).saturating_add(self.0).max(0) as u64
+  }
+}
+
+impl<'de> Deserialize<'de> for TimeSpan {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    deserializer.deserialize_any(TimeSpanVisitor)
+  }
+}
+
+struct TimeSpanVisitor;
+
+impl<'de> Visitor<'de> for TimeSpanVisitor {
+  type Value = TimeSpan;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("a number of seconds or string representing a timespan")
+  }
+
+  fn visit_i64<E: de::Error>(self, value: i64) -> Result<TimeSpan, E> {
+    Ok(TimeSpan(value))
+  }
+
+  fn visit_u64<E: de::Error>(self, value: u64) -> Result<TimeSpan, E> {
+    Ok(TimeSpan(i64::try_from(value).unwrap_or(i64::MAX)))
+  }
+
+  fn visit_f64<E: de::Error>(self, value: f64) -> Result<TimeSpan, E> {
+    if !value.is_finite() {
+      return Err(E::invalid_value(de::Unexpected::Float(value), &self));
+    }
+    Ok(TimeSpan(value.floor() as i64))
+  }
+
+  fn visit_str<E: de::Error>(self, value: &str) -> Result<TimeSpan, E> {
+    parse(value)
+      .map(|milliseconds| TimeSpan((milliseconds / SECOND).floor() as i64))
+      .ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+  }
+}
+
+/// A non-negative count of seconds, like a timestamp,
+/// a fractional number such as `Date.now() / 1000` is floored like the npm package does
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Seconds(pub u64);
+
+impl<'de> Deserialize<'de> for Seconds {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    deserializer.deserialize_any(SecondsVisitor)
+  }
+}
+
+struct SecondsVisitor;
+
+impl<'de> Visitor<'de> for SecondsVisitor {
+  type Value = Seconds;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("a non-negative number of seconds")
+  }
+
+  fn visit_i64<E: de::Error>(self, value: i64) -> Result<Seconds, E> {
+    u64::try_from(value)
+      .map(Seconds)
+      .map_err(|_| E::invalid_value(de::Unexpected::Signed(value), &self))
+  }
+
+  fn visit_u64<E: de::Error>(self, value: u64) -> Result<Seconds, E> {
+    Ok(Seconds(value))
+  }
+
+  fn visit_f64<E: de::Error>(self, value: f64) -> Result<Seconds, E> {
+    // `u64::MAX as f64` rounds up to 2^64, which does not fit
+    if !(0.0..u64::MAX as f64).contains(&value) {
+      return Err(E::invalid_value(de::Unexpected::Float(value), &self));
+    }
+    Ok(Seconds(value.floor() as u64))
+  }
+}
+
+/// A non-negative duration with millisecond precision,
+/// deserialized from a number of seconds or a time span string
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval(pub Duration);
+
+impl Interval {
+  fn from_milliseconds(milliseconds: f64) -> Option<Self> {
+    if milliseconds.is_nan() || milliseconds < 0.0 {
+      return None;
+    }
+    // longer than a `Duration` holds, for the option to reject as out of range
+    let duration = Duration::try_from_secs_f64(milliseconds / SECOND).unwrap_or(Duration::MAX);
+    Some(Interval(duration))
+  }
+}
+
+impl<'de> Deserialize<'de> for Interval {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    deserializer.deserialize_any(IntervalVisitor)
+  }
+}
+
+struct IntervalVisitor;
+
+impl<'de> Visitor<'de> for IntervalVisitor {
+  type Value = Interval;
+
+  fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    formatter.write_str("a non-negative number of seconds or string representing a timespan")
+  }
+
+  fn visit_i64<E: de::Error>(self, value: i64) -> Result<Interval, E> {
+    self.visit_f64(value as f64)
+  }
+
+  fn visit_u64<E: de::Error>(self, value: u64) -> Result<Interval, E> {
+    self.visit_f64(value as f64)
+  }
+
+  fn visit_f64<E: de::Error>(self, value: f64) -> Result<Interval, E> {
+    Interval::from_milliseconds(value * SECOND)
+      .ok_or_else(|| E::invalid_value(de::Unexpected::Float(value), &self))
+  }
+
+  fn visit_str<E: de::Error>(self, value: &str) -> Result<Interval, E> {
+    parse(value)
+      .and_then(Interval::from_milliseconds)
+      .ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  #[test]
+  fn test_parse_units() {
+    assert_eq!(parse("100"), Some(100.0));
+    assert_eq!(parse("1ms"), Some(1.0));
+    assert_eq!(parse("1s"), Some(SECOND));
+    assert_eq!(parse("1m"), Some(MINUTE));
+    assert_eq!(parse("1h"), Some(HOUR));
+    assert_eq!(parse("2d"), Some(2.0 * DAY));
+    assert_eq!(parse("3w"), Some(3.0 * WEEK));
+    assert_eq!(parse("1y"), Some(YEAR));
+  }
+
+  #[test]
+  fn test_parse_long_forms() {
+    assert_eq!(parse("53 milliseconds"), Some(53.0));
+    assert_eq!(parse("17 msecs"), Some(17.0));
+    assert_eq!(parse("1 sec"), Some(SECOND));
+    assert_eq!(parse("1 min"), Some(MINUTE));
+    assert_eq!(parse("1 hr"), Some(HOUR));
+    assert_eq!(parse("2 days"), Some(2.0 * DAY));
+    assert_eq!(parse("1 week"), Some(WEEK));
+    assert_eq!(parse("1 year"), Some(YEAR));
+    assert_eq!(parse("10 HOURS"), Some(10.0 * HOUR));
+  }
+
+  #[test]
+  fn test_parse_decimals_and_negatives() {
+    assert_eq!(parse("1.5h"), Some(1.5 * HOUR));
+    assert_eq!(parse(".5ms"), Some(0.5));
+    assert_eq!(parse("-3 days"), Some(-3.0 * DAY));
+    assert_eq!(parse("-.5h"), Some(-0.5 * HOUR));
+  }
+
+  #[test]
+  fn test_parse_invalid() {
+    assert_eq!(parse(""), None);
+    assert_eq!(parse("☃"), None);
+    assert_eq!(parse("10-.5"), None);
+    assert_eq!(parse("1."), None);
+    assert_eq!(parse("ms"), None);
+    assert_eq!(parse("1 fortnight"), None);
+    assert_eq!(parse(&"1".repeat(101)), None);
+  }
+
+  #[test]
+  fn test_time_span_after() {
+    assert_eq!(TimeSpan(60).after(1000), 1060);
+    assert_eq!(TimeSpan(-60).after(1000), 940);
+    assert_eq!(TimeSpan(-2000).after(1000), 0);
+  }
+
+  #[test]
+  fn test_seconds() {
+    let seconds = |value| serde_json::from_value::<Seconds>(value).map(|seconds| seconds.0);
+    assert_eq!(seconds(json!(1700000000)).unwrap(), 1700000000);
+    assert_eq!(seconds(json!(1700000000.789)).unwrap(), 1700000000);
+    assert_eq!(seconds(json!(0.5)).unwrap(), 0);
+    assert!(seconds(json!(-1)).is_err());
+    assert!(seconds(json!(-0.5)).is_err());
+    assert!(seconds(json!(1e20)).is_err());
+    assert!(seconds(json!("1700000000")).is_err());
+  }
+
+  #[test]
+  fn test_interval() {
+    let interval = |value| serde_json::from_value::<Interval>(value).map(|interval| interval.0);
+    assert_eq!(interval(json!(30)).unwrap(), Duration::from_secs(30));
+    assert_eq!(interval(json!(1.5)).unwrap(), Duration::from_millis(1500));
+    assert_eq!(
+      interval(json!("500ms")).unwrap(),
+      Duration::from_millis(500)
+    );
+    assert_eq!(interval(json!("10m")).unwrap(), Duration::from_secs(600));
+    assert!(interval(json!(-1)).is_err());
+    assert!(interval(json!("-1s")).is_err());
+  }
+
+  #[test]
+  fn test_interval_out_of_range() {
+    let interval = |value| serde_json::from_value::<Interval>(value).map(|interval| interval.0);
+    assert_eq!(interval(json!(1e20)).unwrap(), Duration::MAX);
+    assert_eq!(
+      interval(json!("999999999999999999999y")).unwrap(),
+      Duration::MAX
+    );
+    assert_eq!(
+      IntervalVisitor
+        .visit_f64::<serde_json::Error>(f64::INFINITY)
+        .unwrap()
+        .0,
+      Duration::MAX
+    );
+    assert!(IntervalVisitor
+      .visit_f64::<serde_json::Error>(f64::NAN)
+      .is_err());
+  }
+}
@@ -1,3 +1,12 @@
+use crate::errors::{Error, ErrorKind, OrThrow, Result};
+use crate::jsonwebtoken_mod::key_material::{KeyMaterial, KeySource, RawKey, RawKeySource};
+use crate::jsonwebtoken_mod::remote_jwks::RemoteJwks;
+use crate::neon_serde;
+use neon::prelude::*;
+use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn now() -> u64 {
@@ -6,3 +15,244 @@ pub fn now() -> u64 {
 
   since_the_epoch.as_secs()
 }
+
+/// Deserializes an optional options argument, `undefined` and `null` give the defaults
+pub fn parse_options<'j, C, T>(cx: &mut C, options: Option<Handle<'j, JsValue>>) -> Result<T>
+where
+  C: Context<'j>,
+  T: DeserializeOwned + Default,
+{
+  let options: Option<T> = neon_serde::from_value_opt(cx, options)?;
+  Ok(options.unwrap_or_default())
+}
+
+/// Reads a key argument, a secret or a key in any format `KeyMaterial::from_bytes` reads
+/// as a string or `Buffer`, a `{ key, passphrase }` object or a JWK object,
+/// `index.js` turns a `Uint8Array` into a `Buffer` and exports a `KeyObject`.
+/// `null` is no key, for unsecured tokens
+pub fn key_argument(
+  cx: &mut FunctionContext,
+  index: i32,
+  name: &'static str,
+) -> NeonResult<Option<KeyMaterial>> {
+  let key = raw_key_argument(cx, index, name)?;
+  key.map(RawKey::parse).transpose().or_throw(cx)
+}
+
+/// Reads a key like `key_argument`, from a value that is not an argument
+pub fn key_value<'a, C: Context<'a>>(
+  cx: &mut C,
+  key: Handle<'a, JsValue>,
+  name: &'static str,
+) -> NeonResult<Option<KeyMaterial>> {
+  let key = raw_key_value(cx, key, name)?;
+  key.map(RawKey::parse).transpose().or_throw(cx)
+}
+
+/// Reads a key argument like `key_argument`, but leaves reading its format to the thread pool
+pub fn raw_key_argument(
+  cx: &mut FunctionContext,
+  index: i32,
+  name: &'static str,
+) -> NeonResult<Option<RawKey>> {
+  let key = cx.argument::<JsValue>(index)?;
+  raw_key_value(cx, key, name)
+}
+
+fn raw_key_value<'a, C: Context<'a>>(
+  cx: &mut C,
+  key: Handle<'a, JsValue>,
+  name: &'static str,
+) -> NeonResult<Option<RawKey>> {
+  if key.is_a::<JsNull, _>(cx) {
+    return Ok(None);
+  }
+  if let Some(bytes) = bytes_value(cx, key) {
+    return Ok(Some(RawKey::Bytes(bytes, None)));
+  }
+  if let Ok(object) = key.downcast::<JsObject, _>(cx) {
+    // `{ key, passphrase }` for an encrypted private key, like the npm package
+    let encrypted = object.get(cx, "key")?;
+    if let Some(bytes) = bytes_value(cx, encrypted) {
+      let passphrase = object.get(cx, "passphrase")?;
+      let passphrase = match bytes_value(cx, passphrase) {
+        Some(passphrase) => Some(passphrase),
+        None if passphrase.is_a::<JsUndefined, _>(cx) => None,
+        None => {
+          return Error::from(ErrorKind::InvalidArgument(
+            "passphrase",
+            "must be a string or Buffer",
+          ))
+          .throw(cx)
+        }
+      };
+      return Ok(Some(RawKey::Bytes(bytes, passphrase)));
+    }
+  }
+  if key.is_a::<JsObject, _>(cx) && !key.is_a::<JsArray, _>(cx) {
+    let jwk = neon_serde::from_value(cx, key).or_throw(cx)?;
+    return Ok(Some(RawKey::Jwk(Box::new(jwk))));
+  }
+
+  Error::from(ErrorKind::InvalidArgument(
+    name,
+    "must be a string, Buffer, Uint8Array, KeyObject or JWK",
+  ))
+  .throw(cx)
+}
+
+/// The bytes of a string or `Buffer`
+fn bytes_value<'a, C: Context<'a>>(cx: &mut C, value: Handle<'a, JsValue>) -> Option<Vec<u8>> {
+  if let Ok(value) = value.downcast::<JsString, _>(cx) {
+    return Some(value.value(cx).into_bytes());
+  }
+  if let Ok(value) = value.downcast::<JsBuffer, _>(cx) {
+    return Some(cx.borrow(&value, |data| data.as_slice::<u8>().to_vec()));
+  }
+  None
+}
+
+/// Reads the key argument of `verify`, like `remote_key_source_argument`
+/// but without a remote JWK Set, which would block the main thread while it fetches
+pub fn key_source_argument(
+  cx: &mut FunctionContext,
+  index: i32,
+  name: &'static str,
+) -> NeonResult<Option<KeySource>> {
+  match remote_key_source_argument(cx, index, name)? {
+    Some(RawKeySource::Remote(_)) => Error::from(ErrorKind::InvalidArgument(
+      name,
+      "can only be a remote JWK Set with verifyAsync or a callback",
+    ))
+    .throw(cx),
+    key => key.map(RawKeySource::parse).transpose().or_throw(cx),
+  }
+}
+
+/// Reads the key argument of `verifyAsync`, a remote JWK Set from `createRemoteJwks`,
+/// a JWK Set (`{keys: [...]}`) or what `raw_key_argument` reads
+pub fn remote_key_source_argument(
+  cx: &mut FunctionContext,
+  index: i32,
+  name: &'static str,
+) -> NeonResult<Option<RawKeySource>> {
+  let key = cx.argument::<JsValue>(index)?;
+
+  if let Ok(remote) = key.downcast::<JsBox<Arc<RemoteJwks>>, _>(cx) {
+    return Ok(Some(RawKeySource::Remote(Arc::clone(&remote))));
+  }
+  if let Ok(object) = key.downcast::<JsObject, _>(cx) {
+    if object.get(cx, "keys")?.is_a::<JsArray, _>(cx) {
+      let set = neon_serde::from_value(cx, key).or_throw(cx)?;
+      return Ok(Some(RawKeySource::Set(set)));
+    }
+  }
+
+  Ok(raw_key_argument(cx, index, name)?.map(RawKeySource::Key))
+}
+
+/// The options of `signMany` and `verifyMany` on top of those of `sign` and `verify`
+#[derive(Debug, Default, Deserialize)]
+pub struct BatchOptions {
+  /// spread the batch across the rayon thread pool
+  parallel: Option<bool>,
+}
+
+impl BatchOptions {
+  /// Maps every item of a batch, in order
+  pub fn map<T, R, F>(&self, items: Vec<T>, f: F) -> Vec<R>
+  where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Send + Sync,
+  {
+    if self.parallel.unwrap_or(false) {
+      items.into_par_iter().map(f).collect()
+    } else {
+      items.into_iter().map(f).collect()
+    }
+  }
+}
+
+/// A javascript array of the results of a batch, a failed item gives its error object
+pub fn to_results_array<'a, C, T, F>(
+  cx: &mut C,
+  results: Vec<Result<T>>,
+  to_js: F,
+) -> JsResult<'a, JsArray>
+where
+  C: Context<'a>,
+  F: Fn(&mut C, T) -> JsResult<'a, JsValue>,
+{
+  let array = JsArray::new(cx, results.len() as u32);
+  for (index, result) in results.into_iter().enumerate() {
+    let value = match result {
+      Ok(value) => to_js(cx, value)?,
+      Err(error) => error.to_js_error(cx)?.upcast(),
+    };
+    array.set(cx, index as u32, value)?;
+  }
+
+  Ok(array)
+}
+
+/// A javascript option that takes either a single value or an array of them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+  One(T),
+  Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+  pub fn into_vec(self) -> Vec<T> {
+    match self {
+      OneOrMany::One(value) => vec![value],
+      OneOrMany::Many(values) => values,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn batch(parallel: bool) -> BatchOptions {
+    BatchOptions {
+      parallel: Some(parallel),
+    }
+  }
+
+  #[test]
+  fn test_batch_keeps_order_and_errors_in_place() {
+    let items: Vec<u64> = (0..64).collect();
+    for parallel in [false, true] {
+      let results: Vec<Result<u64>> = batch(parallel).map(items.clone(), |item| {
+        if item == 7 {
+          bail!(ErrorKind::InvalidToken);
+        }
+        Ok(item * 2)
+      });
+
+      assert_eq!(results.len(), items.len());
+      for (item, result) in items.iter().zip(&results) {
+        match result {
+          Ok(value) => assert_eq!(*value, item * 2),
+          Err(error) => {
+            assert_eq!(*item, 7);
+            assert!(matches!(error.kind(), ErrorKind::InvalidToken));
+          }
+        }
+      }
+      assert_eq!(results.iter().filter(|result| result.is_err()).count(), 1);
+    }
+  }
+
+  #[test]
+  fn test_batch_is_sequential_by_default() {
+    let options: BatchOptions = serde_json::from_str("{}").unwrap();
+    let thread = std::thread::current().id();
+    let threads = options.map(vec![1, 2, 3], |_| std::thread::current().id());
+    assert!(threads.iter().all(|id| *id == thread));
+  }
+}
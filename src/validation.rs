@@ -0,0 +1,335 @@
+//! Claim checks `jsonwebtoken::Validation` can not express,
+//! they run on the claims of a token whose signature was already verified
+
+use crate::errors::{ErrorKind, Result};
+use crate::timespan::TimeSpan;
+use crate::utils::{now, OneOrMany};
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// An accepted `aud` as given in the options, `index.js` turns a `RegExp` into its `source` and `flags`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AudienceOption {
+  Exact(String),
+  Pattern { source: String, flags: String },
+}
+
+#[derive(Debug, Clone)]
+enum AudienceMatcher {
+  Exact(String),
+  Pattern(Regex, String),
+}
+
+impl AudienceMatcher {
+  fn new(option: AudienceOption) -> Result<Self> {
+    match option {
+      AudienceOption::Exact(audience) => Ok(AudienceMatcher::Exact(audience)),
+      AudienceOption::Pattern { source, flags } => {
+        // `g` and `u` do not change whether a `test` matches, from a `lastIndex` of 0
+        // `y` anchors the match at the start
+        let inline_flags: String = flags.chars().filter(|flag| "ims".contains(*flag)).collect();
+        let mut pattern = if flags.contains('y') {
+          format!(r"\A(?:{})", source)
+        } else {
+          source.clone()
+        };
+        if !inline_flags.is_empty() {
+          pattern = format!("(?{}){}", inline_flags, pattern);
+        }
+        let regex = Regex::new(&pattern).map_err(|error| {
+          ErrorKind::InvalidOption(
+            "audience",
+            format!("/{}/{} is not supported: {}", source, flags, error),
+          )
+        })?;
+
+        Ok(AudienceMatcher::Pattern(
+          regex,
+          format!("/{}/{}", source, flags),
+        ))
+      }
+    }
+  }
+
+  fn matches(&self, audience: &str) -> bool {
+    match self {
+      AudienceMatcher::Exact(expected) => expected == audience,
+      AudienceMatcher::Pattern(regex, _) => regex.is_match(audience),
+    }
+  }
+
+  fn to_expected(&self) -> String {
+    match self {
+      AudienceMatcher::Exact(expected) => expected.clone(),
+      AudienceMatcher::Pattern(_, display) => display.clone(),
+    }
+  }
+}
+
+/// What `verify` checks in the claims, with the semantics of the npm `jsonwebtoken` package:
+/// a claim that is not required by an option is only checked when the token has it
+#[derive(Debug, Clone)]
+pub struct ClaimValidation {
+  /// reject the token when it has an `exp` that has passed, on by default
+  pub validate_exp: bool,
+  /// reject the token when it has an `nbf` that has not been reached, on by default
+  pub validate_nbf: bool,
+  /// require `iss` to be one of these
+  pub iss: Option<Vec<String>>,
+  /// require `sub` to be this
+  pub sub: Option<String>,
+  /// require `jti` to be this
+  pub jti: Option<String>,
+  /// require `nonce` to be this, for OpenID Connect ID tokens
+  pub nonce: Option<String>,
+  /// reject the token once its `iat` is older than this
+  pub max_age: Option<TimeSpan>,
+  /// seconds of clock skew tolerated by the `exp`, `nbf` and `max_age` checks
+  pub leeway: u64,
+  /// check the time based claims as of this timestamp instead of now
+  pub clock_timestamp: Option<u64>,
+  aud: Option<Vec<AudienceMatcher>>,
+}
+
+impl Default for ClaimValidation {
+  fn default() -> Self {
+    ClaimValidation {
+      validate_exp: true,
+      validate_nbf: true,
+      iss: None,
+      sub: None,
+      jti: None,
+      nonce: None,
+      max_age: None,
+      leeway: 0,
+      clock_timestamp: None,
+      aud: None,
+    }
+  }
+}
+
+impl ClaimValidation {
+  /// Require `aud` to match one of these
+  pub fn set_audience(&mut self, audience: OneOrMany<AudienceOption>) -> Result<()> {
+    let matchers = audience
+      .into_vec()
+      .into_iter()
+      .map(AudienceMatcher::new)
+      .collect::<Result<Vec<_>>>()?;
+    self.aud = Some(matchers);
+    Ok(())
+  }
+
+  pub fn validate(&self, claims: &Map<String, Value>) -> Result<()> {
+    self.validate_at(claims, self.clock_timestamp.unwrap_or_else(now))
+  }
+
+  /// Validates the claims as of `timestamp`, in seconds since the epoch
+  pub fn validate_at(&self, claims: &Map<String, Value>, timestamp: u64) -> Result<()> {
+    let leeway = self.leeway as f64;
+
+    if self.validate_nbf {
+      if let Some(nbf) = numeric_claim(claims, "nbf")? {
+        if nbf > timestamp as f64 + leeway {
+          bail!(ErrorKind::NotBefore(nbf as u64));
+        }
+      }
+    }
+
+    if self.validate_exp {
+      if let Some(exp) = numeric_claim(claims, "exp")? {
+        if timestamp as f64 >= exp + leeway {
+          bail!(ErrorKind::TokenExpired(exp as u64));
+        }
+      }
+    }
+
+    if let Some(matchers) = &self.aud {
+      let actual = claims.get("aud");
+      let audiences: Vec<&str> = match actual {
+        Some(Value::String(audience)) => vec![audience],
+        Some(Value::Array(audiences)) => audiences.iter().filter_map(Value::as_str).collect(),
+        _ => vec![],
+      };
+
+      let matched = audiences
+        .iter()
+        .any(|audience| matchers.iter().any(|matcher| matcher.matches(audience)));
+      if !matched {
+        let expected = matchers.iter().map(AudienceMatcher::to_expected).collect();
+        bail!(ErrorKind::InvalidAudience(expected, actual.cloned()));
+      }
+    }
+
+    if let Some(issuers) = &self.iss {
+      let actual = claims.get("iss");
+      let matched = actual
+        .and_then(Value::as_str)
+        .is_some_and(|issuer| issuers.iter().any(|expected| expected == issuer));
+      if !matched {
+        bail!(ErrorKind::InvalidIssuer(issuers.clone(), actual.cloned()));
+      }
+    }
+
+    if let Some(subject) = &self.sub {
+      if let Err(actual) = exact_claim(claims, "sub", subject) {
+        bail!(ErrorKind::InvalidSubject(subject.clone(), actual));
+      }
+    }
+
+    if let Some(jwtid) = &self.jti {
+      if let Err(actual) = exact_claim(claims, "jti", jwtid) {
+        bail!(ErrorKind::InvalidJwtId(jwtid.clone(), actual));
+      }
+    }
+
+    if let Some(nonce) = &self.nonce {
+      if let Err(actual) = exact_claim(claims, "nonce", nonce) {
+        bail!(ErrorKind::InvalidNonce(nonce.clone(), actual));
+      }
+    }
+
+    if let Some(max_age) = self.max_age {
+      let iat = match numeric_claim(claims, "iat")? {
+        Some(iat) => iat as u64,
+        None => bail!(ErrorKind::MaxAgeWithoutIat),
+      };
+      let max_age_timestamp = max_age.after(iat);
+      if timestamp >= max_age_timestamp.saturating_add(self.leeway) {
+        bail!(ErrorKind::MaxAgeExceeded(max_age_timestamp));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// Checks a string claim, the `Err` has what the token had instead
+fn exact_claim(
+  claims: &Map<String, Value>,
+  name: &str,
+  expected: &str,
+) -> std::result::Result<(), Option<Value>> {
+  let actual = claims.get(name);
+  if actual.and_then(Value::as_str) == Some(expected) {
+    Ok(())
+  } else {
+    Err(actual.cloned())
+  }
+}
+
+/// A NumericDate claim, `None` when the token does not have it
+fn numeric_claim(claims: &Map<String, Value>, name: &'static str) -> Result<Option<f64>> {
+  match claims.get(name) {
+    None => Ok(None),
+    Some(value) => match value.as_f64() {
+      Some(timestamp) => Ok(Some(timestamp)),
+      None => bail!(ErrorKind::InvalidClaim(name)),
+    },
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use serde_json::json;
+
+  fn pattern(source: &str, flags: &str) -> AudienceOption {
+    AudienceOption::Pattern {
+      source: source.to_string(),
+      flags: flags.to_string(),
+    }
+  }
+
+  fn claims(claims: Value) -> Map<String, Value> {
+    match claims {
+      Value::Object(claims) => claims,
+      _ => unreachable!(),
+    }
+  }
+
+  fn with_audience(audience: OneOrMany<AudienceOption>) -> ClaimValidation {
+    let mut validation = ClaimValidation::default();
+    validation.set_audience(audience).unwrap();
+    validation
+  }
+
+  #[test]
+  fn test_audience_matcher() {
+    let matches = |option, audience| AudienceMatcher::new(option).unwrap().matches(audience);
+
+    assert!(matches(AudienceOption::Exact("urn:a".to_string()), "urn:a"));
+    assert!(!matches(
+      AudienceOption::Exact("urn:a".to_string()),
+      "urn:ab"
+    ));
+    assert!(matches(pattern("^urn:a", ""), "urn:ab"));
+    assert!(!matches(pattern("^urn:a", ""), "URN:A"));
+    assert!(matches(pattern("^urn:a", "i"), "URN:A"));
+    assert!(matches(pattern("abc", "g"), "xabc"));
+    // sticky from a `lastIndex` of 0, like `/abc/y.test("xabc")`
+    assert!(!matches(pattern("abc", "y"), "xabc"));
+    assert!(matches(pattern("abc", "y"), "abcx"));
+    assert!(matches(pattern("a|b", "y"), "b"));
+    assert!(!matches(pattern("abc", "my"), "x\nabc"));
+
+    let matcher = AudienceMatcher::new(pattern("^urn:", "i")).unwrap();
+    assert_eq!(matcher.to_expected(), "/^urn:/i");
+    assert!(AudienceMatcher::new(pattern("(?<=a)b", "")).is_err());
+  }
+
+  #[test]
+  fn test_audience() {
+    let validation = with_audience(OneOrMany::Many(vec![
+      AudienceOption::Exact("urn:a".to_string()),
+      pattern("^urn:b:", ""),
+    ]));
+
+    assert!(validation
+      .validate(&claims(json!({"aud": "urn:a"})))
+      .is_ok());
+    assert!(validation
+      .validate(&claims(json!({"aud": "urn:b:1"})))
+      .is_ok());
+    assert!(validation
+      .validate(&claims(json!({"aud": ["urn:c", "urn:b:2"]})))
+      .is_ok());
+
+    let error = validation
+      .validate(&claims(json!({"aud": ["urn:c", "urn:d"]})))
+      .unwrap_err();
+    match error.kind() {
+      ErrorKind::InvalidAudience(expected, actual) => {
+        assert_eq!(
+          expected,
+          &vec!["urn:a".to_string(), "/^urn:b:/".to_string()]
+        );
+        assert_eq!(actual, &Some(json!(["urn:c", "urn:d"])));
+      }
+      kind => panic!("unexpected {:?}", kind),
+    }
+    assert!(validation.validate(&claims(json!({}))).is_err());
+
+    let validation = with_audience(OneOrMany::One(AudienceOption::Exact("urn:a".to_string())));
+    assert!(validation
+      .validate(&claims(json!({"aud": ["urn:b", "urn:a"]})))
+      .is_ok());
+  }
+
+  #[test]
+  fn test_issuer() {
+    let validation = ClaimValidation {
+      iss: Some(vec!["a".to_string(), "b".to_string()]),
+      ..ClaimValidation::default()
+    };
+
+    assert!(validation.validate(&claims(json!({"iss": "b"}))).is_ok());
+    let error = validation
+      .validate(&claims(json!({"iss": "c"})))
+      .unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::InvalidIssuer(..)));
+    assert!(validation.validate(&claims(json!({}))).is_err());
+    assert!(validation.validate(&claims(json!({"iss": ["a"]}))).is_err());
+  }
+}
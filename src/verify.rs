@@ -1,102 +1,414 @@
-use crate::jsonwebtoken_mod::algorithm::Key;
+use crate::errors::{ErrorKind, OrThrow, Result};
+use crate::jsonwebtoken_mod::algorithm::Algorithm;
+use crate::jsonwebtoken_mod::decoding::{decode, DecodingKeys, TokenData};
 use crate::jsonwebtoken_mod::header::ToObject;
+use crate::jsonwebtoken_mod::key_material::{KeySource, RawKeySource};
 use crate::neon_serde;
-use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use crate::task::{spawn_on, Runner};
+use crate::timespan::{Seconds, TimeSpan};
+use crate::utils::{
+  key_source_argument, parse_options, remote_key_source_argument, to_results_array, BatchOptions,
+  OneOrMany,
+};
+use crate::validation::{AudienceOption, ClaimValidation};
 use neon::prelude::*;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::sync::Arc;
 
 pub fn verify(mut cx: FunctionContext) -> JsResult<JsValue> {
   let jwt = cx.argument::<JsString>(0)?.value(&mut cx);
-  let key = cx.argument::<JsString>(1)?.value(&mut cx);
+  let key = key_source_argument(&mut cx, 1, "secretOrPublicKey")?;
   let options = cx.argument_opt(2);
-  let decode_options: VerifyOptions =
-    neon_serde::from_value_opt(&mut cx, options).unwrap_or(VerifyOptions::default());
+  let decode_options: VerifyOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
 
-  let validation = decode_options.to_validation();
-  let key = decode_options.get_key(key.as_bytes());
+  let payload = decode_options
+    .verify(&jwt, key.as_ref())
+    .or_throw(&mut cx)?;
 
-  let payload = decode::<Claims>(&jwt, &key, &validation).unwrap();
-  let claim_object = neon_serde::to_value(&mut cx, &payload.claims).unwrap();
+  to_verify_result(&mut cx, &jwt, payload, decode_options.complete())
+}
+
+/// Like `verify`, but parses the key and verifies on the thread pool,
+/// then calls back `callback(error, payload)`. When the key is in a remote JWK Set
+/// that has to be fetched first, the verification waits for the fetch off the pool
+pub fn verify_async(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+  let jwt = cx.argument::<JsString>(0)?.value(&mut cx);
+  let key = remote_key_source_argument(&mut cx, 1, "secretOrPublicKey")?;
+  let options = cx.argument_opt(2);
+  let callback = cx.argument::<JsFunction>(3)?;
+  let decode_options: VerifyOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+  let complete = decode_options.complete();
+  let runner = match &key {
+    Some(RawKeySource::Remote(remote)) if remote.needs_fetch(&jwt) => {
+      Runner::AfterFetch(Arc::clone(remote))
+    }
+    _ => Runner::Pool,
+  };
+
+  spawn_on(
+    runner,
+    &mut cx,
+    callback,
+    move || {
+      let key = key.map(RawKeySource::parse).transpose()?;
+      Ok((decode_options.verify(&jwt, key.as_ref())?, jwt))
+    },
+    move |cx, (payload, jwt)| to_verify_result(cx, &jwt, payload, complete),
+  );
+
+  Ok(cx.undefined())
+}
+
+/// A key and options parsed once, `index.js` wraps the box in a `Verifier`
+pub struct Verifier {
+  keys: DecodingKeys,
+  claim_validation: ClaimValidation,
+  complete: bool,
+}
+
+impl Finalize for Verifier {}
+
+impl Verifier {
+  fn new(key: Option<KeySource>, options: &VerifyOptions) -> Result<Self> {
+    Ok(Verifier {
+      keys: DecodingKeys::new(key, &options.algorithms, options.allow_insecure_key_sizes())?,
+      claim_validation: options.to_claim_validation()?,
+      complete: options.complete(),
+    })
+  }
+
+  fn verify(&self, jwt: &str) -> Result<TokenData<Claims>> {
+    let payload = self.keys.decode::<Claims>(jwt)?;
+    self.claim_validation.validate(&payload.claims.extra)?;
+
+    Ok(payload)
+  }
+}
+
+pub fn create_verifier(mut cx: FunctionContext) -> JsResult<JsBox<Verifier>> {
+  let key = key_source_argument(&mut cx, 0, "secretOrPublicKey")?;
+  let options = cx.argument_opt(1);
+  let decode_options: VerifyOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+
+  let verifier = Verifier::new(key, &decode_options).or_throw(&mut cx)?;
 
-  if !decode_options.complete.unwrap_or(false) {
+  Ok(cx.boxed(verifier))
+}
+
+pub fn verifier_verify(mut cx: FunctionContext) -> JsResult<JsValue> {
+  let verifier = cx.argument::<JsBox<Verifier>>(0)?;
+  let jwt = cx.argument::<JsString>(1)?.value(&mut cx);
+
+  let payload = verifier.verify(&jwt).or_throw(&mut cx)?;
+
+  to_verify_result(&mut cx, &jwt, payload, verifier.complete)
+}
+
+/// Verifies every token with the key and options parsed once,
+/// a token that does not verify gives its error in place of the payload
+pub fn verify_many(mut cx: FunctionContext) -> JsResult<JsArray> {
+  let tokens = cx.argument::<JsArray>(0)?.to_vec(&mut cx)?;
+  let key = key_source_argument(&mut cx, 1, "secretOrPublicKey")?;
+  let options = cx.argument_opt(2);
+
+  let decode_options: VerifyOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+  let batch_options: BatchOptions = parse_options(&mut cx, options).or_throw(&mut cx)?;
+  let verifier = Verifier::new(key, &decode_options).or_throw(&mut cx)?;
+
+  let tokens: Vec<Result<String>> = tokens
+    .into_iter()
+    .map(|jwt| match jwt.downcast::<JsString, _>(&mut cx) {
+      Ok(jwt) => Ok(jwt.value(&mut cx)),
+      Err(_) => Err(ErrorKind::InvalidArgument("jwt", "must be a string").into()),
+    })
+    .collect();
+  let payloads = batch_options.map(tokens, |jwt| {
+    let jwt = jwt?;
+    let payload = verifier.verify(&jwt)?;
+    Ok((jwt, payload))
+  });
+
+  to_results_array(&mut cx, payloads, |cx, (jwt, payload)| {
+    to_verify_result(cx, &jwt, payload, verifier.complete)
+  })
+}
+
+/// The claims, or the whole token when `complete` is set
+fn to_verify_result<'a, C: Context<'a>>(
+  cx: &mut C,
+  jwt: &str,
+  payload: TokenData<Claims>,
+  complete: bool,
+) -> JsResult<'a, JsValue> {
+  let claim_object = neon_serde::to_value(cx, &payload.claims).or_throw(cx)?;
+
+  if !complete {
     return Ok(claim_object);
   }
 
   let decode_result = cx.empty_object();
-  decode_result.set(&mut cx, "payload", claim_object)?;
+  decode_result.set(cx, "payload", claim_object)?;
 
-  let header = payload.header.to_object(&mut cx);
+  let header = payload.header.to_object(cx)?;
 
-  decode_result.set(&mut cx, "header", header)?;
-  let signature = cx.string(jwt.split(".").nth(2).unwrap().to_string());
-  decode_result.set(&mut cx, "signature", signature)?;
+  decode_result.set(cx, "header", header)?;
+  let signature = cx.string(jwt.split('.').nth(2).unwrap_or_default());
+  decode_result.set(cx, "signature", signature)?;
 
-  Ok(
-    decode_result
-      .downcast_or_throw::<JsValue, _>(&mut cx)
-      .unwrap(),
-  )
+  Ok(decode_result.upcast())
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Claims {
   #[serde(flatten)]
-  extra: HashMap<String, Value>,
+  extra: Map<String, Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct VerifyOptions {
+  #[serde(default = "default_algorithms")]
   algorithms: Vec<Algorithm>,
-  audience: Option<Vec<String>>,
+  audience: Option<OneOrMany<AudienceOption>>,
   complete: Option<bool>,
-  issuer: Option<String>,
+  issuer: Option<OneOrMany<String>>,
   jwtid: Option<String>,
   ignore_expiration: Option<bool>,
   ignore_not_before: Option<bool>,
   subject: Option<String>,
+  /// the `nonce` an OpenID Connect ID token must have
+  nonce: Option<String>,
+  /// seconds of clock skew tolerated when checking `exp`, `nbf` and `maxAge`
+  clock_tolerance: Option<Seconds>,
+  /// the time to check the token as of, in seconds since the epoch
+  clock_timestamp: Option<Seconds>,
+  /// the oldest `iat` accepted, in seconds or a string describing a time span vercel/ms
+  max_age: Option<TimeSpan>,
+  /// accept secrets shorter than the hash output and RSA moduli under 2048 bits, for legacy keys
+  allow_insecure_key_sizes: Option<bool>,
 }
 
 impl VerifyOptions {
-  fn to_validation(&self) -> Validation {
-    let mut validation = Validation {
-      algorithms: self.algorithms.clone(),
-      iss: self.issuer.clone(),
-      sub: self.subject.clone(),
-      validate_exp: self.ignore_expiration.unwrap_or(true),
-      validate_nbf: self.ignore_not_before.unwrap_or(false),
-      ..Default::default()
-    };
-
-    if self.audience.is_some() {
-      validation.set_audience(&self.audience.clone().unwrap().as_slice());
-    }
+  /// Checks the signature of `jwt` with `key`, then its claims
+  fn verify(&self, jwt: &str, key: Option<&KeySource>) -> Result<TokenData<Claims>> {
+    let claim_validation = self.to_claim_validation()?;
+    let payload = decode::<Claims>(jwt, key, &self.algorithms, self.allow_insecure_key_sizes())?;
+    claim_validation.validate(&payload.claims.extra)?;
 
-    validation
+    Ok(payload)
   }
 
-  fn get_key<'a>(&self, key: &'a [u8]) -> DecodingKey<'a> {
-    for alg in &self.algorithms {
-      return alg.get_decoding_key(key);
+  fn complete(&self) -> bool {
+    self.complete.unwrap_or(false)
+  }
+
+  fn allow_insecure_key_sizes(&self) -> bool {
+    self.allow_insecure_key_sizes.unwrap_or(false)
+  }
+
+  /// `ignoreExpiration` and `ignoreNotBefore` turn off the `exp` and `nbf` checks,
+  /// both of which run by default whenever the token has the claim
+  fn to_claim_validation(&self) -> Result<ClaimValidation> {
+    let mut validation = ClaimValidation::default();
+    validation.validate_exp = !self.ignore_expiration.unwrap_or(false);
+    validation.validate_nbf = !self.ignore_not_before.unwrap_or(false);
+    validation.iss = self.issuer.clone().map(OneOrMany::into_vec);
+    validation.sub = self.subject.clone();
+    validation.jti = self.jwtid.clone();
+    validation.nonce = self.nonce.clone();
+    validation.max_age = self.max_age;
+    validation.leeway = self.clock_tolerance.map_or(0, |tolerance| tolerance.0);
+    validation.clock_timestamp = self.clock_timestamp.map(|timestamp| timestamp.0);
+
+    if let Some(audience) = &self.audience {
+      validation.set_audience(audience.clone())?;
     }
 
-    DecodingKey::from_secret(key)
+    Ok(validation)
   }
 }
 
+fn default_algorithms() -> Vec<Algorithm> {
+  vec![Algorithm::HS256]
+}
+
 impl Default for VerifyOptions {
   fn default() -> Self {
     VerifyOptions {
-      algorithms: vec![Algorithm::HS256],
+      algorithms: default_algorithms(),
       audience: None,
       complete: Some(false),
       issuer: None,
       jwtid: None,
-      ignore_expiration: Some(true),
+      ignore_expiration: Some(false),
       ignore_not_before: Some(false),
       subject: None,
+      nonce: None,
+      clock_tolerance: None,
+      clock_timestamp: None,
+      max_age: None,
+      allow_insecure_key_sizes: None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::errors::ErrorKind;
+  use serde_json::json;
+
+  const NOW: u64 = 1_600_000_000;
+
+  fn claims(exp: Option<u64>, nbf: Option<u64>) -> Map<String, Value> {
+    let mut claims = Map::new();
+    if let Some(exp) = exp {
+      claims.insert("exp".to_string(), Value::from(exp));
+    }
+    if let Some(nbf) = nbf {
+      claims.insert("nbf".to_string(), Value::from(nbf));
+    }
+    claims
+  }
+
+  fn options(ignore_expiration: Option<bool>, ignore_not_before: Option<bool>) -> VerifyOptions {
+    let mut options = json!({});
+    if let Some(ignore) = ignore_expiration {
+      options["ignoreExpiration"] = Value::from(ignore);
+    }
+    if let Some(ignore) = ignore_not_before {
+      options["ignoreNotBefore"] = Value::from(ignore);
+    }
+    serde_json::from_value(options).unwrap()
+  }
+
+  #[test]
+  fn test_default_options_check_exp_and_nbf() {
+    let validation = VerifyOptions::default().to_claim_validation().unwrap();
+    assert!(validation.validate_exp);
+    assert!(validation.validate_nbf);
+  }
+
+  #[test]
+  fn test_ignore_expiration_and_not_before_matrix() {
+    let flags = [None, Some(false), Some(true)];
+    let exps = [None, Some(NOW - 1), Some(NOW), Some(NOW + 1)];
+    let nbfs = [None, Some(NOW - 1), Some(NOW), Some(NOW + 1)];
+
+    for &ignore_expiration in &flags {
+      for &ignore_not_before in &flags {
+        let validation = options(ignore_expiration, ignore_not_before)
+          .to_claim_validation()
+          .unwrap();
+
+        for &exp in &exps {
+          for &nbf in &nbfs {
+            let result = validation.validate_at(&claims(exp, nbf), NOW);
+            let case = format!(
+              "ignoreExpiration: {:?}, ignoreNotBefore: {:?}, exp: {:?}, nbf: {:?}",
+              ignore_expiration, ignore_not_before, exp, nbf
+            );
+
+            let not_active =
+              !ignore_not_before.unwrap_or(false) && nbf.is_some_and(|nbf| nbf > NOW);
+            let expired = !ignore_expiration.unwrap_or(false) && exp.is_some_and(|exp| exp <= NOW);
+
+            match result {
+              Err(error) => match error.kind() {
+                ErrorKind::NotBefore(date) => {
+                  assert!(not_active, "{}", case);
+                  assert_eq!(Some(*date), nbf, "{}", case);
+                }
+                ErrorKind::TokenExpired(expired_at) => {
+                  assert!(!not_active && expired, "{}", case);
+                  assert_eq!(Some(*expired_at), exp, "{}", case);
+                }
+                kind => panic!("{}: unexpected {:?}", case, kind),
+              },
+              Ok(()) => assert!(!not_active && !expired, "{}", case),
+            }
+          }
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_invalid_exp_and_nbf_values() {
+    let validation = VerifyOptions::default().to_claim_validation().unwrap();
+    for name in &["exp", "nbf"] {
+      let mut claims = Map::new();
+      claims.insert(name.to_string(), Value::from("tomorrow"));
+      let error = validation.validate_at(&claims, NOW).unwrap_err();
+      assert_eq!(error.to_string(), format!("invalid {} value", name));
     }
   }
+
+  #[test]
+  fn test_clock_tolerance_clock_timestamp_and_max_age() {
+    let options: VerifyOptions = serde_json::from_value(json!({
+      "clockTolerance": 10,
+      "clockTimestamp": NOW,
+      "maxAge": "1h",
+    }))
+    .unwrap();
+    let validation = options.to_claim_validation().unwrap();
+
+    let mut claims = claims(Some(NOW - 5), Some(NOW + 5));
+    claims.insert("iat".to_string(), Value::from(NOW - 3600));
+    assert!(validation.validate(&claims).is_ok());
+
+    claims.insert("iat".to_string(), Value::from(NOW - 3610));
+    let error = validation.validate(&claims).unwrap_err();
+    assert!(
+      matches!(error.kind(), ErrorKind::MaxAgeExceeded(expired_at) if *expired_at == NOW - 10)
+    );
+
+    claims.remove("iat");
+    let error = validation.validate(&claims).unwrap_err();
+    assert!(matches!(error.kind(), ErrorKind::MaxAgeWithoutIat));
+  }
+
+  #[test]
+  fn test_fractional_clock_timestamp_and_tolerance() {
+    // like `Date.now() / 1000`
+    let options: VerifyOptions = serde_json::from_value(json!({
+      "clockTolerance": 1.5,
+      "clockTimestamp": NOW as f64 + 0.999,
+    }))
+    .unwrap();
+    let validation = options.to_claim_validation().unwrap();
+    assert_eq!(validation.clock_timestamp, Some(NOW));
+    assert_eq!(validation.leeway, 1);
+
+    assert!(validation.validate(&claims(Some(NOW), None)).is_ok());
+    assert!(validation.validate(&claims(Some(NOW - 1), None)).is_err());
+  }
+
+  #[test]
+  fn test_jwtid_and_nonce() {
+    let options: VerifyOptions = serde_json::from_value(json!({
+      "jwtid": "id-1",
+      "nonce": "n-0S6_WzA2Mj",
+    }))
+    .unwrap();
+    let validation = options.to_claim_validation().unwrap();
+
+    let mut claims = Map::new();
+    claims.insert("jti".to_string(), Value::from("id-1"));
+    claims.insert("nonce".to_string(), Value::from("n-0S6_WzA2Mj"));
+    assert!(validation.validate_at(&claims, NOW).is_ok());
+
+    claims.insert("nonce".to_string(), Value::from("replayed"));
+    let error = validation.validate_at(&claims, NOW).unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "jwt nonce invalid. expected: n-0S6_WzA2Mj"
+    );
+
+    claims.remove("jti");
+    let error = validation.validate_at(&claims, NOW).unwrap_err();
+    assert_eq!(error.to_string(), "jwt jwtid invalid. expected: id-1");
+  }
 }